@@ -1,11 +1,12 @@
 use std::sync::Arc;
 use std::thread::JoinHandle;
-use std::u128;
+use std::time::{Duration, Instant};
 
 use criterion::{criterion_group, criterion_main, Criterion};
 use flume::{Receiver, Sender};
 use futures::StreamExt;
-use sharded_thread::mesh::MeshBuilder;
+use sharded_thread::mesh::{MeshBuilder, Transport};
+use sharded_thread::shard::{SendError, Shard};
 
 cfg_if::cfg_if! {
     if #[cfg(target_os = "linux")] {
@@ -22,15 +23,16 @@ cfg_if::cfg_if! {
 //
 // We send a value from 3 -> 1, then 1 -> 2, then 2 -> 3.
 fn start_threads<Msg: Send + 'static>(
+    transport: Transport,
 ) -> (Vec<JoinHandle<()>>, Arc<MeshBuilder<Msg>>) {
     use std::sync::Arc;
 
-    use futures::StreamExt;
-    use sharded_thread::mesh::MeshBuilder;
-    use sharded_thread::shard::Shard;
-
     let cpus = 3;
-    let mesh = Arc::new(MeshBuilder::<Msg>::new(cpus).unwrap());
+    let mesh = Arc::new(
+        MeshBuilder::<Msg>::new(cpus)
+            .unwrap()
+            .with_transport(transport),
+    );
 
     let mut handles: Vec<std::thread::JoinHandle<()>> = Vec::new();
     for peer in 0..2 {
@@ -54,7 +56,7 @@ fn start_threads<Msg: Send + 'static>(
                     let send_to = (peer + 1) % cpus;
 
                     while let Some(val) = receiver.next().await {
-                        shard.send_to_unchecked(val, send_to);
+                        forward(&shard, val, send_to);
                     }
                 });
                 handle.await
@@ -67,8 +69,25 @@ fn start_threads<Msg: Send + 'static>(
     (handles, mesh)
 }
 
+/// Send `val` to `to`, spinning while its ring is full with
+/// `Transport::Rings`.
+fn forward<Msg>(shard: &Shard<Msg>, mut val: Msg, to: usize) {
+    loop {
+        match shard.send_to_unchecked(val, to) {
+            Ok(()) => return,
+            Err(SendError::Full(back)) => {
+                val = back;
+                std::hint::spin_loop();
+            }
+            Err(error) => panic!("the value couldn't be forwarded: {error}"),
+        }
+    }
+}
+
+type FlumeChannels<Msg> = Vec<(Sender<Msg>, Receiver<Msg>)>;
+
 fn start_threads_flume<Msg: Send + 'static>(
-) -> (Vec<JoinHandle<()>>, Vec<(Sender<Msg>, Receiver<Msg>)>) {
+) -> (Vec<JoinHandle<()>>, FlumeChannels<Msg>) {
     // 1 -> 2
     let (tx1, rx2) = flume::unbounded::<Msg>();
     // 2 -> 3
@@ -179,7 +198,7 @@ fn execute_round<T: Send + 'static>(
                     if count > count_max {
                         return;
                     }
-                    shard.send_to_unchecked(val, 0);
+                    forward(&shard, val, 0);
                     count += 1;
                 }
             });
@@ -189,13 +208,13 @@ fn execute_round<T: Send + 'static>(
 }
 
 fn bench_round(c: &mut Criterion) {
-    let (_, mesh) = start_threads::<usize>();
+    let (_, mesh) = start_threads::<usize>(Transport::Sharded);
 
     c.bench_function("send_a_value_between_two_cpu_1", |b| {
         b.iter_batched(
             || (Arc::clone(&mesh), execute_round(Arc::clone(&mesh), 0)),
             |(mesh, handle)| {
-                mesh.send_to(1, 12345).unwrap();
+                mesh.send_to(12345, mesh.peer_id(1).unwrap()).unwrap();
                 handle.join().unwrap();
             },
             criterion::BatchSize::PerIteration,
@@ -206,7 +225,7 @@ fn bench_round(c: &mut Criterion) {
         b.iter_batched(
             || (Arc::clone(&mesh), execute_round(Arc::clone(&mesh), 1)),
             |(mesh, handle)| {
-                mesh.send_to(2, 12345).unwrap();
+                mesh.send_to(12345, mesh.peer_id(2).unwrap()).unwrap();
                 handle.join().unwrap();
             },
             criterion::BatchSize::PerIteration,
@@ -217,7 +236,7 @@ fn bench_round(c: &mut Criterion) {
         b.iter_batched(
             || (Arc::clone(&mesh), execute_round(Arc::clone(&mesh), 10)),
             |(mesh, handle)| {
-                mesh.send_to(2, 12345).unwrap();
+                mesh.send_to(12345, mesh.peer_id(2).unwrap()).unwrap();
                 handle.join().unwrap();
             },
             criterion::BatchSize::PerIteration,
@@ -228,7 +247,7 @@ fn bench_round(c: &mut Criterion) {
         b.iter_batched(
             || (Arc::clone(&mesh), execute_round(Arc::clone(&mesh), 100)),
             |(mesh, handle)| {
-                mesh.send_to(2, 12345).unwrap();
+                mesh.send_to(12345, mesh.peer_id(2).unwrap()).unwrap();
                 handle.join().unwrap();
             },
             criterion::BatchSize::PerIteration,
@@ -239,7 +258,7 @@ fn bench_round(c: &mut Criterion) {
         b.iter_batched(
             || (Arc::clone(&mesh), execute_round(Arc::clone(&mesh), 1_000)),
             |(mesh, handle)| {
-                mesh.send_to(2, 12345).unwrap();
+                mesh.send_to(12345, mesh.peer_id(2).unwrap()).unwrap();
                 handle.join().unwrap();
             },
             criterion::BatchSize::PerIteration,
@@ -250,7 +269,44 @@ fn bench_round(c: &mut Criterion) {
         b.iter_batched(
             || (Arc::clone(&mesh), execute_round(Arc::clone(&mesh), 10_000)),
             |(mesh, handle)| {
-                mesh.send_to(2, 12345).unwrap();
+                mesh.send_to(12345, mesh.peer_id(2).unwrap()).unwrap();
+                handle.join().unwrap();
+            },
+            criterion::BatchSize::PerIteration,
+        )
+    });
+}
+
+fn bench_round_rings(c: &mut Criterion) {
+    let (_, mesh) = start_threads::<usize>(Transport::Rings);
+
+    c.bench_function("rings_send_a_value_between_two_cpu_1", |b| {
+        b.iter_batched(
+            || (Arc::clone(&mesh), execute_round(Arc::clone(&mesh), 0)),
+            |(mesh, handle)| {
+                mesh.send_to(12345, mesh.peer_id(1).unwrap()).unwrap();
+                handle.join().unwrap();
+            },
+            criterion::BatchSize::PerIteration,
+        )
+    });
+
+    c.bench_function("rings_rotate_a_usize_between_3_cpu_100", |b| {
+        b.iter_batched(
+            || (Arc::clone(&mesh), execute_round(Arc::clone(&mesh), 100)),
+            |(mesh, handle)| {
+                mesh.send_to(12345, mesh.peer_id(2).unwrap()).unwrap();
+                handle.join().unwrap();
+            },
+            criterion::BatchSize::PerIteration,
+        )
+    });
+
+    c.bench_function("rings_rotate_a_usize_between_3_cpu_10_000", |b| {
+        b.iter_batched(
+            || (Arc::clone(&mesh), execute_round(Arc::clone(&mesh), 10_000)),
+            |(mesh, handle)| {
+                mesh.send_to(12345, mesh.peer_id(2).unwrap()).unwrap();
                 handle.join().unwrap();
             },
             criterion::BatchSize::PerIteration,
@@ -258,6 +314,47 @@ fn bench_round(c: &mut Criterion) {
     });
 }
 
+// Two shards send values to a third one at the same time: with
+// `Transport::Rings` they push to their own ring but still contend on the
+// count of the queue of the receiving shard.
+fn bench_fan_in(c: &mut Criterion) {
+    for (name, transport) in [
+        ("fan_in_2_to_1", Transport::Sharded),
+        ("rings_fan_in_2_to_1", Transport::Rings),
+    ] {
+        let mesh = MeshBuilder::<usize>::new(3)
+            .unwrap()
+            .with_transport(transport);
+
+        c.bench_function(name, |b| b.iter_custom(|iters| fan_in(&mesh, iters)));
+    }
+}
+
+/// Send `iters` values from the shards 0 and 1 to the shard 2.
+fn fan_in(mesh: &MeshBuilder<usize>, iters: u64) -> Duration {
+    let start = Instant::now();
+
+    std::thread::scope(|scope| {
+        for peer in 0..2 {
+            scope.spawn(move || {
+                let shard: Shard<usize> = mesh.join_with(peer).unwrap();
+                for val in 0..iters as usize {
+                    forward(&shard, val, 2);
+                }
+            });
+        }
+
+        let shard: Shard<usize> = mesh.join_with(2).unwrap();
+        let mut receiver = shard.receiver().unwrap();
+        for _ in 0..2 * iters {
+            receiver.recv_blocking().unwrap();
+        }
+    });
+
+    start.elapsed()
+}
+
+#[allow(dead_code)]
 struct WrapperSendStruct {
     val: usize,
     thing: String,
@@ -286,13 +383,17 @@ impl Default for WrapperSendStruct {
 
 // TODO: MAcro to generate benches
 fn bench_round_struct(c: &mut Criterion) {
-    let (_, mesh) = start_threads::<WrapperSendStruct>();
+    let (_, mesh) = start_threads::<WrapperSendStruct>(Transport::Sharded);
 
     c.bench_function("struct_send_a_value_between_two_cpu_1", |b| {
         b.iter_batched(
             || (Arc::clone(&mesh), execute_round(Arc::clone(&mesh), 0)),
             |(mesh, handle)| {
-                mesh.send_to(1, WrapperSendStruct::default()).unwrap();
+                mesh.send_to(
+                    WrapperSendStruct::default(),
+                    mesh.peer_id(1).unwrap(),
+                )
+                .unwrap();
                 handle.join().unwrap();
             },
             criterion::BatchSize::PerIteration,
@@ -303,7 +404,11 @@ fn bench_round_struct(c: &mut Criterion) {
         b.iter_batched(
             || (Arc::clone(&mesh), execute_round(Arc::clone(&mesh), 1)),
             |(mesh, handle)| {
-                mesh.send_to(1, WrapperSendStruct::default()).unwrap();
+                mesh.send_to(
+                    WrapperSendStruct::default(),
+                    mesh.peer_id(1).unwrap(),
+                )
+                .unwrap();
                 handle.join().unwrap();
             },
             criterion::BatchSize::PerIteration,
@@ -314,7 +419,11 @@ fn bench_round_struct(c: &mut Criterion) {
         b.iter_batched(
             || (Arc::clone(&mesh), execute_round(Arc::clone(&mesh), 10)),
             |(mesh, handle)| {
-                mesh.send_to(1, WrapperSendStruct::default()).unwrap();
+                mesh.send_to(
+                    WrapperSendStruct::default(),
+                    mesh.peer_id(1).unwrap(),
+                )
+                .unwrap();
                 handle.join().unwrap();
             },
             criterion::BatchSize::PerIteration,
@@ -325,7 +434,11 @@ fn bench_round_struct(c: &mut Criterion) {
         b.iter_batched(
             || (Arc::clone(&mesh), execute_round(Arc::clone(&mesh), 100)),
             |(mesh, handle)| {
-                mesh.send_to(1, WrapperSendStruct::default()).unwrap();
+                mesh.send_to(
+                    WrapperSendStruct::default(),
+                    mesh.peer_id(1).unwrap(),
+                )
+                .unwrap();
                 handle.join().unwrap();
             },
             criterion::BatchSize::PerIteration,
@@ -336,7 +449,11 @@ fn bench_round_struct(c: &mut Criterion) {
         b.iter_batched(
             || (Arc::clone(&mesh), execute_round(Arc::clone(&mesh), 1_000)),
             |(mesh, handle)| {
-                mesh.send_to(1, WrapperSendStruct::default()).unwrap();
+                mesh.send_to(
+                    WrapperSendStruct::default(),
+                    mesh.peer_id(1).unwrap(),
+                )
+                .unwrap();
                 handle.join().unwrap();
             },
             criterion::BatchSize::PerIteration,
@@ -347,7 +464,11 @@ fn bench_round_struct(c: &mut Criterion) {
         b.iter_batched(
             || (Arc::clone(&mesh), execute_round(Arc::clone(&mesh), 1_000)),
             |(mesh, handle)| {
-                mesh.send_to(1, WrapperSendStruct::default()).unwrap();
+                mesh.send_to(
+                    WrapperSendStruct::default(),
+                    mesh.peer_id(1).unwrap(),
+                )
+                .unwrap();
                 handle.join().unwrap();
             },
             criterion::BatchSize::PerIteration,
@@ -465,6 +586,6 @@ fn bench_round_flume(c: &mut Criterion) {
 criterion_group! {
     name = benches;
     config = Criterion::default();
-    targets = bench_round, bench_round_struct, bench_round_flume,
+    targets = bench_round, bench_round_struct, bench_round_rings, bench_fan_in, bench_round_flume,
 }
 criterion_main!(benches);
@@ -0,0 +1,212 @@
+//! Synchronization point shared by every shard of a mesh.
+//!
+//! A [`ShardBarrier`] is created from the mesh, every shard waits on it and
+//! they are all released once the last one arrived. The barrier can be reused
+//! right away for the next synchronization point.
+
+use std::sync::{Arc, Mutex};
+use std::task::{Poll, Waker};
+
+use crate::membership::{Membership, Registration};
+use crate::shard::Shard;
+
+/// Error returned when the shards couldn't meet at a barrier.
+#[derive(Debug, thiserror::Error)]
+pub enum BarrierError {
+    #[error("A peer left the mesh while waiting on the barrier.")]
+    PeerLeft,
+    #[error("The mesh was shutdown while waiting on the barrier.")]
+    Closed,
+    #[error("The shard is already waiting on the barrier.")]
+    AlreadyWaiting,
+    #[error("The shard is not part of the mesh of the barrier.")]
+    WrongMesh,
+}
+
+/// Result of a successful wait on a [`ShardBarrier`].
+#[derive(Debug, Clone, Copy)]
+pub struct BarrierWaitResult {
+    generation: u64,
+    leader: bool,
+}
+
+impl BarrierWaitResult {
+    /// Number of times the barrier released the shards before this wait.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Only the last shard arriving at the barrier is the leader.
+    pub fn is_leader(&self) -> bool {
+        self.leader
+    }
+}
+
+/// A reusable barrier between every peer of a mesh.
+///
+/// Waiting doesn't block the thread of the shard, its executor keeps running
+/// other tasks. Every peer of the mesh must wait on the barrier for it to
+/// release the shards, the wait fails if a peer leaves the mesh meanwhile or
+/// if the mesh is shutdown.
+#[derive(Clone)]
+pub struct ShardBarrier {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    parties: usize,
+    membership: Arc<Membership>,
+    state: Mutex<State>,
+}
+
+struct State {
+    generation: u64,
+    arrived: usize,
+    /// Peers which arrived for the current generation.
+    waiting: Vec<bool>,
+    wakers: Vec<Waker>,
+}
+
+impl std::fmt::Debug for ShardBarrier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ShardBarrier")
+    }
+}
+
+impl ShardBarrier {
+    pub(crate) fn new(parties: usize, membership: Arc<Membership>) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                parties,
+                membership,
+                state: Mutex::new(State {
+                    generation: 0,
+                    arrived: 0,
+                    waiting: vec![false; parties],
+                    wakers: Vec::new(),
+                }),
+            }),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.inner
+            .state
+            .lock()
+            .expect("the barrier lock should not be poisoned")
+    }
+
+    /// Wait until every peer of the mesh arrived at the barrier with its
+    /// `shard`.
+    ///
+    /// Fail if a peer is not part of the mesh or leaves it before the barrier
+    /// releases the shards, the barrier can still be used for the next
+    /// synchronization point. Fail as well once the mesh is shutdown, when
+    /// `shard` is already waiting on the barrier or belongs to another mesh.
+    pub async fn wait<T>(
+        &self,
+        shard: &Shard<T>,
+    ) -> Result<BarrierWaitResult, BarrierError> {
+        if !Arc::ptr_eq(&shard.membership, &self.inner.membership) {
+            return Err(BarrierError::WrongMesh);
+        }
+
+        let peer = shard.shard_id;
+        let departures = self.inner.membership.departures();
+
+        if self.inner.membership.is_closed() {
+            return Err(BarrierError::Closed);
+        }
+
+        // A peer which already left would never arrive.
+        if self.inner.membership.len() < self.inner.parties {
+            return Err(BarrierError::PeerLeft);
+        }
+
+        let generation = {
+            let mut state = self.state();
+            let generation = state.generation;
+            if state.waiting[peer] {
+                return Err(BarrierError::AlreadyWaiting);
+            }
+            state.waiting[peer] = true;
+            state.arrived += 1;
+
+            if state.arrived == self.inner.parties {
+                state.generation += 1;
+                state.arrived = 0;
+                state.waiting.fill(false);
+                for waker in std::mem::take(&mut state.wakers) {
+                    waker.wake();
+                }
+
+                return Ok(BarrierWaitResult {
+                    generation,
+                    leader: true,
+                });
+            }
+
+            generation
+        };
+
+        // Withdraw from the barrier if the wait is dropped before the release.
+        let mut arrival = Arrival {
+            barrier: self,
+            peer,
+            generation,
+            released: false,
+            leave: self.inner.membership.on_leave(),
+        };
+
+        futures::future::poll_fn(|cx| {
+            arrival.leave.register(cx.waker());
+
+            let mut state = self.state();
+            if state.generation != generation {
+                arrival.released = true;
+                return Poll::Ready(Ok(BarrierWaitResult {
+                    generation,
+                    leader: false,
+                }));
+            }
+
+            if self.inner.membership.departures() != departures {
+                return Poll::Ready(Err(BarrierError::PeerLeft));
+            }
+
+            if self.inner.membership.is_closed() {
+                return Poll::Ready(Err(BarrierError::Closed));
+            }
+
+            if !state.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                state.wakers.push(cx.waker().clone());
+            }
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+/// A shard waiting on the barrier.
+struct Arrival<'a> {
+    barrier: &'a ShardBarrier,
+    peer: usize,
+    generation: u64,
+    released: bool,
+    /// Registration to be woken when a peer leaves the mesh.
+    leave: Registration<'a>,
+}
+
+impl Drop for Arrival<'_> {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+
+        let mut state = self.barrier.state();
+        if state.generation == self.generation {
+            state.waiting[self.peer] = false;
+            state.arrived -= 1;
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! Group the values sent to each shard into batches.
+//!
+//! Sending a batch wakes the receiving shard once instead of once per value,
+//! which matters when a shard forwards a lot of small values.
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::shard::{BroadcastError, SendError, Shard, Target};
+
+/// A buffer holding the values sent by a [`Shard`] to each other shard until
+/// they are sent as a batch.
+///
+/// The values for a shard are sent once `threshold` of them are buffered or
+/// when the buffer is flushed. The buffer must be flushed before it is dropped,
+/// the values which couldn't be sent are given back by the flush. Dropping a
+/// buffer still holding values drops them and panics in debug builds.
+pub struct SendBuffer<'a, T> {
+    shard: &'a Shard<T>,
+    threshold: usize,
+    pending: RefCell<Vec<Vec<T>>>,
+}
+
+impl<T> std::fmt::Debug for SendBuffer<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SendBuffer")
+    }
+}
+
+impl<'a, T> SendBuffer<'a, T> {
+    pub(crate) fn new(shard: &'a Shard<T>, threshold: usize) -> Self {
+        Self {
+            shard,
+            threshold: threshold.max(1),
+            pending: RefCell::new(
+                (0..shard.senders.len()).map(|_| Vec::new()).collect(),
+            ),
+        }
+    }
+
+    /// Buffer a value for a shard, by its index or by its
+    /// [`ShardId`](crate::shard::ShardId). The buffered values of the shard
+    /// are sent once `threshold` of them are buffered.
+    ///
+    /// Fail if the shard doesn't exist, if its id belongs to another mesh or
+    /// if the batch couldn't be sent, the values of the batch are given back.
+    pub fn push(
+        &self,
+        val: T,
+        shard: impl Target,
+    ) -> Result<(), SendError<Vec<T>>> {
+        let Some(shard) = self.index_of(shard) else {
+            return Err(SendError::WrongShard(vec![val]));
+        };
+
+        let full = {
+            let mut pending = self.pending.borrow_mut();
+            let Some(batch) = pending.get_mut(shard) else {
+                return Err(SendError::WrongShard(vec![val]));
+            };
+
+            batch.push(val);
+            batch.len() >= self.threshold
+        };
+
+        if full {
+            self.flush_to(shard)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Send the values buffered for a shard, by its index or by its
+    /// [`ShardId`](crate::shard::ShardId).
+    pub fn flush_to(
+        &self,
+        shard: impl Target,
+    ) -> Result<(), SendError<Vec<T>>> {
+        let batch = match self.index_of(shard).and_then(|shard| {
+            self.pending.borrow_mut().get_mut(shard).map(std::mem::take)
+        }) {
+            Some(batch) => batch,
+            None => return Err(SendError::WrongShard(Vec::new())),
+        };
+
+        self.shard.send_batch_to(batch, shard)
+    }
+
+    /// Get the index of `shard`, `None` if it is the id of a shard of another
+    /// mesh.
+    fn index_of(&self, shard: impl Target) -> Option<usize> {
+        self.shard.membership.peer_of(shard)
+    }
+
+    /// Send the values buffered for every shard.
+    pub fn flush(&self) -> Result<(), BroadcastError<Vec<T>>> {
+        let mut failures = Vec::new();
+        self.flush_into(&mut failures);
+
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(BroadcastError { failures })
+        }
+    }
+
+    fn flush_into(&self, failures: &mut Vec<(usize, SendError<Vec<T>>)>) {
+        for shard in 0..self.shard.senders.len() {
+            if self.pending.borrow()[shard].is_empty() {
+                continue;
+            }
+
+            if let Err(error) = self.flush_to(shard) {
+                failures.push((shard, error));
+            }
+        }
+    }
+
+    /// Run `future` and flush the buffer each time it is polled, so the
+    /// values it buffered are sent before the task yields to the executor.
+    ///
+    /// It resolves to the output of `future` with the batches which couldn't
+    /// be sent while it ran.
+    pub fn flush_on_poll<F: Future>(
+        &self,
+        future: F,
+    ) -> FlushOnPoll<'_, 'a, T, F> {
+        FlushOnPoll {
+            buffer: self,
+            future: Box::pin(future),
+            failures: Vec::new(),
+        }
+    }
+}
+
+impl<T> Drop for SendBuffer<'_, T> {
+    fn drop(&mut self) {
+        if !std::thread::panicking() {
+            debug_assert!(
+                self.pending.get_mut().iter().all(Vec::is_empty),
+                "the send buffer must be flushed before it is dropped"
+            );
+        }
+    }
+}
+
+/// Future returned by [`SendBuffer::flush_on_poll`].
+#[must_use = "futures do nothing unless polled"]
+pub struct FlushOnPoll<'b, 'a, T, F> {
+    buffer: &'b SendBuffer<'a, T>,
+    future: Pin<Box<F>>,
+    failures: Vec<(usize, SendError<Vec<T>>)>,
+}
+
+impl<T, F> std::fmt::Debug for FlushOnPoll<'_, '_, T, F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "FlushOnPoll")
+    }
+}
+
+// The future is boxed, it is never moved once pinned.
+impl<T, F> Unpin for FlushOnPoll<'_, '_, T, F> {}
+
+impl<T, F: Future> Future for FlushOnPoll<'_, '_, T, F> {
+    type Output = (F::Output, Result<(), BroadcastError<Vec<T>>>);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let output = this.future.as_mut().poll(cx);
+        this.buffer.flush_into(&mut this.failures);
+
+        output.map(|output| {
+            let failures = std::mem::take(&mut this.failures);
+            if failures.is_empty() {
+                (output, Ok(()))
+            } else {
+                (output, Err(BroadcastError { failures }))
+            }
+        })
+    }
+}
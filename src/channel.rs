@@ -0,0 +1,293 @@
+//! Several channels of different types sharing the peers of a mesh.
+//!
+//! A mesh carries values of its own type, it can also host a channel for each
+//! additional type registered with
+//! [`MeshBuilder::with_channel`](crate::mesh::MeshBuilder::with_channel). Every
+//! channel shares the peers of the mesh: a single [`Shard`] joins all of them
+//! at once and reaches each of them with [`Shard::channel`].
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::membership::Membership;
+use crate::queue::{
+    shard_sender, QueueOptions, Receiver, Sender, SharedQueueChannels,
+    SharedQueueThreaded,
+};
+use crate::shard::{SendError, Shard, Target, Unreachable};
+
+/// The queues of every additional channel of a mesh, by type.
+#[derive(Default)]
+pub(crate) struct Channels {
+    queues: HashMap<TypeId, Box<dyn AnyQueues>>,
+}
+
+impl Channels {
+    /// Add a channel for `U`, with a queue for each peer.
+    pub(crate) fn register<U: Send + 'static>(
+        &mut self,
+        nb_cpu: usize,
+        options: impl Fn(usize) -> QueueOptions,
+        nr_peers: usize,
+    ) {
+        let mut queues = Queues::<U> { queues: Vec::new() };
+        queues.rebuild(nb_cpu, &options, nr_peers);
+        self.queues.insert(TypeId::of::<U>(), Box::new(queues));
+    }
+
+    /// Create the queues of every channel again, with new options.
+    pub(crate) fn rebuild(
+        &mut self,
+        nb_cpu: usize,
+        options: &dyn Fn(usize) -> QueueOptions,
+        nr_peers: usize,
+    ) {
+        for queues in self.queues.values_mut() {
+            queues.rebuild(nb_cpu, options, nr_peers);
+        }
+    }
+
+    /// Close the queue of every peer in every channel.
+    pub(crate) fn close(&self) {
+        for queues in self.queues.values() {
+            queues.close();
+        }
+    }
+
+    /// Open the queue of `peer` again in every channel.
+    pub(crate) fn reopen(&self, peer: usize) {
+        for queues in self.queues.values() {
+            queues.reopen(peer);
+        }
+    }
+
+    /// Drop the values waiting for `peer` in every channel.
+    pub(crate) fn clear(&self, peer: usize) {
+        for queues in self.queues.values() {
+            queues.clear(peer);
+        }
+    }
+
+    /// Create the ends used by `peer` in every channel.
+    pub(crate) fn ends(&self, peer: usize) -> ShardChannels {
+        ShardChannels {
+            ends: self
+                .queues
+                .iter()
+                .map(|(type_id, queues)| (*type_id, queues.ends(peer)))
+                .collect(),
+        }
+    }
+}
+
+/// The queues of a channel with its type erased.
+trait AnyQueues: Send + Sync {
+    fn rebuild(
+        &mut self,
+        nb_cpu: usize,
+        options: &dyn Fn(usize) -> QueueOptions,
+        nr_peers: usize,
+    );
+
+    fn close(&self);
+
+    fn reopen(&self, peer: usize);
+
+    fn clear(&self, peer: usize);
+
+    fn ends(&self, peer: usize) -> Box<dyn AnyEnds>;
+}
+
+/// The queue of each peer in a channel of `U`.
+struct Queues<U> {
+    queues: Vec<Arc<SharedQueueThreaded<U>>>,
+}
+
+impl<U: Send + 'static> AnyQueues for Queues<U> {
+    fn rebuild(
+        &mut self,
+        nb_cpu: usize,
+        options: &dyn Fn(usize) -> QueueOptions,
+        nr_peers: usize,
+    ) {
+        self.queues = (0..nr_peers)
+            .map(|peer| {
+                SharedQueueThreaded::with_options(nb_cpu, options(peer))
+            })
+            .collect();
+    }
+
+    fn close(&self) {
+        for queue in &self.queues {
+            queue.close();
+        }
+    }
+
+    fn reopen(&self, peer: usize) {
+        self.queues[peer].reopen();
+    }
+
+    fn clear(&self, peer: usize) {
+        self.queues[peer].clear();
+    }
+
+    fn ends(&self, peer: usize) -> Box<dyn AnyEnds> {
+        let (_, receiver) = self.queues[peer].unbounded();
+        Box::new(Ends {
+            senders: self
+                .queues
+                .iter()
+                .map(|queue| shard_sender(queue, peer))
+                .collect(),
+            receiver: std::cell::Cell::new(Some(receiver)),
+        })
+    }
+}
+
+/// The ends of every additional channel used by a [`Shard`].
+#[derive(Default)]
+pub(crate) struct ShardChannels {
+    ends: HashMap<TypeId, Box<dyn AnyEnds>>,
+}
+
+impl ShardChannels {
+    /// Close the queue of `shard` in every channel.
+    pub(crate) fn close(&self, shard: usize) {
+        for ends in self.ends.values() {
+            ends.close(shard);
+        }
+    }
+
+    /// End the receivers of `shard` in every channel.
+    pub(crate) fn retire_receivers(&self, shard: usize) {
+        for ends in self.ends.values() {
+            ends.retire_receivers(shard);
+        }
+    }
+
+    fn get<U: 'static>(&self) -> Option<&Ends<U>> {
+        self.ends
+            .get(&TypeId::of::<U>())?
+            .as_any()
+            .downcast_ref::<Ends<U>>()
+    }
+}
+
+/// The ends of a channel with their type erased.
+trait AnyEnds: Send {
+    fn close(&self, shard: usize);
+
+    fn retire_receivers(&self, shard: usize);
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// The ends used by a shard in a channel of `U`.
+struct Ends<U> {
+    senders: Vec<Sender<U>>,
+    receiver: std::cell::Cell<Option<Receiver<U>>>,
+}
+
+impl<U: Send + 'static> AnyEnds for Ends<U> {
+    fn close(&self, shard: usize) {
+        self.senders[shard].close();
+    }
+
+    fn retire_receivers(&self, shard: usize) {
+        self.senders[shard].retire_receivers();
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A channel of `U` seen from a [`Shard`], see [`Shard::channel`].
+///
+/// It reaches the same peers as the shard, a peer which joined the mesh can
+/// receive values from every channel.
+pub struct Channel<'a, U> {
+    ends: &'a Ends<U>,
+    membership: &'a Membership,
+    shard_id: usize,
+}
+
+impl<U> std::fmt::Debug for Channel<'_, U> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Channel")
+            .field("shard_id", &self.shard_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a, U: 'static> Channel<'a, U> {
+    pub(crate) fn new<T>(shard: &'a Shard<T>) -> Option<Self> {
+        Some(Self {
+            ends: shard.channels.get()?,
+            membership: &shard.membership,
+            shard_id: shard.shard_id,
+        })
+    }
+}
+
+impl<U> Channel<'_, U> {
+    /// Take the receiver of this shard in the channel.
+    ///
+    /// Only one receiver exists for each shard and each channel.
+    pub fn receiver(&self) -> Option<Receiver<U>> {
+        self.ends.receiver.take()
+    }
+
+    /// Send a value to a shard through the channel, by its index or by its
+    /// [`ShardId`](crate::shard::ShardId).
+    ///
+    /// Fail like [`Shard::send_to`].
+    pub fn send_to(
+        &self,
+        val: U,
+        shard: impl Target,
+    ) -> Result<(), SendError<U>> {
+        match self.joined_sender(shard) {
+            Ok(sender) => sender.try_send(val),
+            Err(unreachable) => Err(unreachable.with(val)),
+        }
+    }
+
+    /// Send a value to a shard through the channel, waiting for the shard to
+    /// make some room if its queue is full.
+    ///
+    /// Fail like [`Shard::send_to_async`].
+    pub async fn send_to_async(
+        &self,
+        val: U,
+        shard: impl Target,
+    ) -> Result<(), SendError<U>> {
+        match self.joined_sender(shard) {
+            Ok(sender) => sender.send_async(val).await,
+            Err(unreachable) => Err(unreachable.with(val)),
+        }
+    }
+
+    /// Get the sender of a shard which joined the mesh.
+    fn joined_sender(
+        &self,
+        shard: impl Target,
+    ) -> Result<&Sender<U>, Unreachable> {
+        let shard = self
+            .membership
+            .peer_of(shard)
+            .ok_or(Unreachable::WrongShard)?;
+        let sender = self
+            .ends
+            .senders
+            .get(shard)
+            .ok_or(Unreachable::WrongShard)?;
+
+        if !self.membership.is_joined(shard) {
+            return Err(Unreachable::NotJoined);
+        }
+
+        Ok(sender)
+    }
+}
@@ -1,6 +1,11 @@
 //! `sharded_thread` is a module to provides any runtime efficient
 //! channels-like abstractions.
 
+pub mod barrier;
+pub mod buffer;
+pub mod channel;
+pub(crate) mod membership;
+
 /// A mesh to connect multiple executor together.
 ///
 /// A mesh is composed of multiple peers and a way to communicate between each
@@ -8,7 +13,8 @@
 ///
 /// # Examples
 ///
-/// A thread-per-core architecture with `monoio`.
+/// A thread-per-core architecture with `monoio`. With the `monoio` feature,
+/// `runtime::MeshRuntime` spawns, pins and runs the threads for you.
 ///
 /// ```rust
 /// use sharded_thread::{mesh::MeshBuilder, shard::Shard};
@@ -48,11 +54,9 @@
 ///           let handle = monoio::spawn(async move {
 ///               let mut receiver = shard.receiver().unwrap();
 ///
-///               // We send it unchecked because we are not sure every shard joined when we send
-///               // it.
-///               // Even if the shard did not join, we can buffer it inside the internal channel.
-///               // It's not unsafe, it's unchecked.
-///               shard.send_to_unchecked(peer, (peer + 1) % cpus);
+///               // Wait for every shard to join so the value can't be refused.
+///               shard.wait_all_joined().await.unwrap();
+///               shard.send_to(peer, (peer + 1) % cpus).unwrap();
 ///
 ///               while let Some(val) = receiver.next().await {
 ///                 println!("Received {val} on CPU {peer}");
@@ -72,7 +76,18 @@
 /// }
 /// ```
 pub mod mesh;
+#[cfg(target_os = "linux")]
+pub mod notify;
 pub(crate) mod queue;
+pub mod remote;
+pub(crate) mod ring;
+pub mod router;
+pub mod rpc;
+#[cfg(feature = "monoio")]
+pub mod runtime;
 
 /// Sharding utilities built on top of a mesh.
 pub mod shard;
+pub mod sink;
+
+pub use queue::{Receiver, RecvMode, RecvTimeoutError, TryRecvError};
@@ -0,0 +1,339 @@
+//! Track which peers are currently part of the mesh.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::task::{Poll, Waker};
+
+use futures::future::Either;
+
+use crate::mesh::{JoinError, MeshClosed, NotReady};
+use crate::shard::{ShardId, Target};
+
+/// Tag of the next mesh, so the ids of its shards can't be used with another
+/// mesh.
+static NEXT_MESH: AtomicU64 = AtomicU64::new(0);
+
+/// Bitmap of the peers which joined the mesh, shared by the mesh and every
+/// shard.
+pub(crate) struct Membership {
+    /// Tag of the mesh, carried by the id of its shards.
+    mesh: u64,
+    joined: Box<[AtomicU64]>,
+    len: AtomicUsize,
+    nr_peers: usize,
+    closed: AtomicBool,
+    /// Set once every peer joined the mesh, it stays set when peers leave.
+    ready: AtomicBool,
+    /// Tasks waiting for every peer to join the mesh.
+    ready_wakers: Wakers,
+    /// Number of times a peer left the mesh.
+    departures: AtomicUsize,
+    /// Tasks waiting for a peer to leave the mesh or for it to close.
+    leave_wakers: Wakers,
+}
+
+impl Membership {
+    pub(crate) fn new(nr_peers: usize) -> Self {
+        Self {
+            mesh: NEXT_MESH.fetch_add(1, Ordering::Relaxed),
+            joined: (0..nr_peers.div_ceil(64))
+                .map(|_| AtomicU64::new(0))
+                .collect(),
+            len: AtomicUsize::new(0),
+            nr_peers,
+            closed: AtomicBool::new(false),
+            ready: AtomicBool::new(false),
+            ready_wakers: Wakers::default(),
+            departures: AtomicUsize::new(0),
+            leave_wakers: Wakers::default(),
+        }
+    }
+
+    /// Get the id of `peer`, `None` if it doesn't exist.
+    pub(crate) fn peer_id(&self, peer: usize) -> Option<ShardId> {
+        (peer < self.nr_peers).then(|| ShardId::new(peer, self.mesh))
+    }
+
+    /// Get the peer of `shard`, `None` if it is the id of a peer of another
+    /// mesh. An index is not checked.
+    pub(crate) fn peer_of(&self, shard: impl Target) -> Option<usize> {
+        shard
+            .mesh()
+            .is_none_or(|mesh| mesh == self.mesh)
+            .then(|| shard.index())
+    }
+
+    /// Word and bit of `peer` in the bitmap.
+    fn position(peer: usize) -> (usize, u64) {
+        (peer / 64, 1 << (peer % 64))
+    }
+
+    /// Mark `peer` as joined, fail if it already joined.
+    pub(crate) fn join(&self, peer: usize) -> Result<(), JoinError> {
+        if peer >= self.nr_peers {
+            return Err(JoinError::OutOfRange {
+                peer,
+                nr_peers: self.nr_peers,
+            });
+        }
+
+        if self.is_closed() {
+            return Err(JoinError::Closed);
+        }
+
+        let (word, bit) = Self::position(peer);
+        let previous = self.joined[word].fetch_or(bit, Ordering::AcqRel);
+
+        if previous & bit != 0 {
+            return Err(JoinError::AlreadyJoined(peer));
+        }
+
+        if self.len.fetch_add(1, Ordering::AcqRel) + 1 == self.nr_peers {
+            self.ready.store(true, Ordering::Release);
+            self.ready_wakers.wake_all();
+        }
+        Ok(())
+    }
+
+    /// Mark the first free peer as joined.
+    pub(crate) fn join_next(&self) -> Result<usize, JoinError> {
+        for peer in 0..self.nr_peers {
+            match self.join(peer) {
+                Err(JoinError::AlreadyJoined(_)) => continue,
+                joined => return joined.map(|()| peer),
+            }
+        }
+
+        Err(JoinError::Full)
+    }
+
+    /// Prevent peers from joining, the tasks waiting for them or for a
+    /// departure are woken.
+    pub(crate) fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.ready_wakers.wake_all();
+        self.leave_wakers.wake_all();
+    }
+
+    /// Check if peers are prevented from joining.
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    /// Mark `peer` as departed.
+    pub(crate) fn leave(&self, peer: usize) {
+        let (word, bit) = Self::position(peer);
+        let previous = self.joined[word].fetch_and(!bit, Ordering::AcqRel);
+
+        if previous & bit != 0 {
+            self.len.fetch_sub(1, Ordering::AcqRel);
+            self.departures.fetch_add(1, Ordering::AcqRel);
+            self.leave_wakers.wake_all();
+        }
+    }
+
+    /// Number of times a peer left the mesh, it only grows.
+    pub(crate) fn departures(&self) -> usize {
+        self.departures.load(Ordering::Acquire)
+    }
+
+    /// Create a registration to wake a task the next time a peer leaves the
+    /// mesh or when it closes.
+    pub(crate) fn on_leave(&self) -> Registration<'_> {
+        Registration::new(&self.leave_wakers)
+    }
+
+    /// Check if `peer` is currently part of the mesh.
+    pub(crate) fn is_joined(&self, peer: usize) -> bool {
+        if peer >= self.nr_peers {
+            return false;
+        }
+
+        let (word, bit) = Self::position(peer);
+        self.joined[word].load(Ordering::Acquire) & bit != 0
+    }
+
+    /// Number of peers currently part of the mesh.
+    pub(crate) fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    /// Ids of the peers which are not part of the mesh.
+    pub(crate) fn missing(&self) -> Vec<usize> {
+        (0..self.nr_peers)
+            .filter(|&peer| !self.is_joined(peer))
+            .collect()
+    }
+
+    /// Check if every peer joined the mesh at some point.
+    fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Acquire)
+    }
+
+    /// Check if every peer joined the mesh, fail if it was closed before.
+    fn poll_ready(&self) -> Poll<Result<(), MeshClosed>> {
+        if self.is_ready() {
+            Poll::Ready(Ok(()))
+        } else if self.is_closed() {
+            Poll::Ready(Err(MeshClosed))
+        } else {
+            Poll::Pending
+        }
+    }
+
+    /// Wait until every peer joined the mesh, fail if it is closed before.
+    pub(crate) async fn ready(&self) -> Result<(), MeshClosed> {
+        // Removed once the wait is over or dropped.
+        let mut registration = Registration::new(&self.ready_wakers);
+
+        futures::future::poll_fn(|cx| {
+            if let Poll::Ready(ready) = self.poll_ready() {
+                return Poll::Ready(ready);
+            }
+
+            registration.register(cx.waker());
+
+            // The last peer could have joined, or the mesh could have been
+            // closed, before we registered.
+            self.poll_ready()
+        })
+        .await
+    }
+
+    /// Wait until every peer joined the mesh or until `delay` resolves.
+    pub(crate) async fn ready_timeout<D>(
+        &self,
+        delay: D,
+    ) -> Result<(), NotReady>
+    where
+        D: Future<Output = ()>,
+    {
+        let ready = std::pin::pin!(self.ready());
+        let delay = std::pin::pin!(delay);
+
+        match futures::future::select(ready, delay).await {
+            Either::Left((Ok(()), _)) => Ok(()),
+            Either::Left((Err(MeshClosed), _)) => Err(NotReady {
+                missing: self.missing(),
+                closed: true,
+            }),
+            Either::Right(((), _)) => Err(NotReady {
+                missing: self.missing(),
+                closed: false,
+            }),
+        }
+    }
+
+    /// Ids of the peers currently part of the mesh.
+    pub(crate) fn members(&self) -> Vec<usize> {
+        (0..self.nr_peers)
+            .filter(|&peer| self.is_joined(peer))
+            .collect()
+    }
+}
+
+/// Tasks waiting for an event, each of them registered under its own key.
+#[derive(Default)]
+struct Wakers {
+    wakers: Mutex<Vec<(u64, Waker)>>,
+    /// Key of the next registration.
+    next_key: AtomicU64,
+}
+
+impl Wakers {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<(u64, Waker)>> {
+        self.wakers
+            .lock()
+            .expect("the wakers lock should not be poisoned")
+    }
+
+    /// Wake every registered task, they must register again to be woken by
+    /// the next event.
+    fn wake_all(&self) {
+        for (_, waker) in std::mem::take(&mut *self.lock()) {
+            waker.wake();
+        }
+    }
+}
+
+/// The waker of a task waiting for an event, it is registered at most once
+/// and removed when the registration is dropped.
+pub(crate) struct Registration<'a> {
+    wakers: &'a Wakers,
+    key: Option<u64>,
+}
+
+impl<'a> Registration<'a> {
+    fn new(wakers: &'a Wakers) -> Self {
+        Self { wakers, key: None }
+    }
+
+    /// Wake `waker` on the next event, in place of the waker registered
+    /// previously.
+    pub(crate) fn register(&mut self, waker: &Waker) {
+        let key = *self.key.get_or_insert_with(|| {
+            self.wakers.next_key.fetch_add(1, Ordering::Relaxed)
+        });
+        let mut wakers = self.wakers.lock();
+
+        match wakers.iter_mut().find(|(registered, _)| *registered == key) {
+            Some((_, registered)) if registered.will_wake(waker) => {}
+            Some((_, registered)) => *registered = waker.clone(),
+            None => wakers.push((key, waker.clone())),
+        }
+    }
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            self.wakers
+                .lock()
+                .retain(|(registered, _)| *registered != key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::task::{Context, Waker};
+
+    use super::Membership;
+
+    #[test]
+    fn ensure_leave_wakers_are_removed_once_done() {
+        let membership = Membership::new(2);
+        let mut first = membership.on_leave();
+        let mut second = membership.on_leave();
+
+        for _ in 0..3 {
+            first.register(Waker::noop());
+            second.register(Waker::noop());
+        }
+        assert_eq!(membership.leave_wakers.lock().len(), 2);
+
+        drop(first);
+        assert_eq!(membership.leave_wakers.lock().len(), 1);
+
+        membership.join(0).unwrap();
+        membership.leave(0);
+        assert_eq!(membership.leave_wakers.lock().len(), 0);
+    }
+
+    #[test]
+    fn ensure_ready_wakers_are_removed_once_dropped() {
+        let membership = Membership::new(2);
+        let mut cx = Context::from_waker(Waker::noop());
+
+        {
+            let mut ready = std::pin::pin!(membership.ready());
+            for _ in 0..3 {
+                assert!(ready.as_mut().poll(&mut cx).is_pending());
+            }
+            assert_eq!(membership.ready_wakers.lock().len(), 1);
+        }
+        assert_eq!(membership.ready_wakers.lock().len(), 0);
+    }
+}
@@ -3,22 +3,146 @@
 
 use std::cell::Cell;
 use std::fmt::Debug;
-use std::sync::atomic::AtomicUsize;
+use std::future::Future;
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
 use std::sync::Arc;
 
-use crate::queue::{SharedQueueChannels, SharedQueueThreaded};
-use crate::shard::{SenderError, Shard};
+use crate::barrier::ShardBarrier;
+use crate::channel::Channels;
+use crate::membership::Membership;
+#[cfg(target_os = "linux")]
+use crate::notify::EventFd;
+use crate::queue::{
+    shard_sender, QueueOptions, RecvMode, SharedQueueChannels,
+    SharedQueueThreaded,
+};
+use crate::remote::Task;
+use crate::router::{Router, Routing};
+use crate::shard::{SendError, Shard, ShardId};
 
 /// A Mesh is a structure which can be shared in every thread by reference to
 /// allow threads to join the Mesh and talk to each others.
-pub struct MeshBuilder<T> {
-    #[allow(dead_code)]
+///
+/// The number of peers is given at runtime by default. A mesh of `N` peers
+/// created with [`MeshBuilder::fixed`] checks at compile time the ids given to
+/// [`MeshBuilder::id`], so an id out of the mesh can't be constructed.
+pub struct MeshBuilder<T, const N: usize = 0> {
     nr_peers: usize,
+    nb_cpu: usize,
+    /// Maximum number of items buffered for each shard, `None` when unbounded.
+    capacity: Option<usize>,
+    /// How values are carried between shards.
+    transport: Transport,
+    /// Number of priorities of the queue of each shard.
+    priorities: usize,
+    /// Eventfd of each shard, when the shards are notified through one.
+    #[cfg(target_os = "linux")]
+    eventfds: Option<Vec<Arc<EventFd>>>,
     pub(crate) channels: Vec<Arc<SharedQueueThreaded<T>>>,
-    pub(crate) shared_joined: Arc<AtomicUsize>,
+    /// Additional channels of other types, see [`MeshBuilder::with_channel`].
+    typed_channels: Channels,
+    /// Tasks spawned on each shard by the other shards.
+    pub(crate) tasks: Vec<Arc<SharedQueueThreaded<Task>>>,
+    /// How keys are routed to shards.
+    pub(crate) routing: Routing,
+    /// Peers which are currently part of the mesh.
+    pub(crate) membership: Arc<Membership>,
 }
 
-impl<T> Debug for MeshBuilder<T> {
+/// How values are carried from a shard to another.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    /// Every shard sending values to a shard pushes them to the same queue.
+    ///
+    /// The values sent by a shard to another can be received in a different
+    /// order.
+    #[default]
+    Sharded,
+    /// Each shard sending values to a shard pushes them to its own bounded
+    /// lock-free ring, the receiving shard pops its rings round-robin.
+    ///
+    /// The senders don't contend with each others and the values sent by a
+    /// shard to another are received in order. The capacity of the mesh is
+    /// the capacity of each ring, 1024 values by default.
+    Rings,
+}
+
+/// Error returned when a peer can't join the mesh.
+#[derive(Debug, thiserror::Error)]
+pub enum JoinError {
+    #[error("The peer {peer} doesn't exist in a mesh of {nr_peers} peers.")]
+    OutOfRange { peer: usize, nr_peers: usize },
+    #[error("The peer {0} already joined the mesh.")]
+    AlreadyJoined(usize),
+    #[error("Every peer already joined the mesh.")]
+    Full,
+    #[error("The mesh is shut down.")]
+    Closed,
+    #[error("The shard id belongs to another mesh.")]
+    WrongMesh,
+}
+
+/// Error returned when the mesh is shut down before every peer joined it.
+#[derive(Debug, thiserror::Error)]
+#[error("The mesh is shut down.")]
+pub struct MeshClosed;
+
+/// Error returned when some peers did not join the mesh in time.
+#[derive(Debug, thiserror::Error)]
+#[error("The peers {missing:?} did not join the mesh {}.", until(*.closed))]
+pub struct NotReady {
+    pub(crate) missing: Vec<usize>,
+    pub(crate) closed: bool,
+}
+
+impl NotReady {
+    /// Ids of the peers which did not join the mesh.
+    pub fn missing(&self) -> &[usize] {
+        &self.missing
+    }
+
+    /// Check if the mesh was shut down before every peer joined it.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+}
+
+fn until(closed: bool) -> &'static str {
+    if closed {
+        "before it was shut down"
+    } else {
+        "in time"
+    }
+}
+
+/// Options used by a peer to join the mesh.
+#[derive(Debug, Default, Clone)]
+pub struct JoinOptions {
+    discard_pending: bool,
+    recv_mode: RecvMode,
+}
+
+impl JoinOptions {
+    /// Drop the values and tasks which were sent to the peer before it
+    /// joined, e.g. while the previous shard with the same id was gone.
+    ///
+    /// They are kept by default, so a replacement shard can handle them.
+    pub fn discard_pending(mut self, discard: bool) -> Self {
+        self.discard_pending = discard;
+        self
+    }
+
+    /// Set how the receiver of the shard waits for values, see [`RecvMode`].
+    ///
+    /// The receiver parks until a value is sent by default.
+    pub fn recv_mode(mut self, mode: RecvMode) -> Self {
+        self.recv_mode = mode;
+        self
+    }
+}
+
+impl<T, const N: usize> Debug for MeshBuilder<T, N> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "MeshBuilder")
     }
@@ -26,68 +150,497 @@ impl<T> Debug for MeshBuilder<T> {
 
 impl<T> MeshBuilder<T> {
     /// Create a new mesh between a number of peers.
+    ///
+    /// Fail if there are no peers.
     pub fn new(nr_peers: usize) -> std::io::Result<Self> {
         let nb_cpu = std::thread::available_parallelism()?.get();
 
         MeshBuilder::with_cpu(nr_peers, nb_cpu)
     }
 
+    pub fn with_cpu(nr_peers: usize, nb_cpu: usize) -> std::io::Result<Self> {
+        Self::build(nr_peers, nb_cpu)
+    }
+
+    /// Join the mesh means you can talk to other peer and peer can talk to you.
+    ///
+    /// You must assign yourself an id so other Shard will be able to talk with
+    /// you using this ID
     ///
-    pub fn members(&self) -> usize {
-        self.shared_joined
-            .load(std::sync::atomic::Ordering::Acquire)
+    /// Fail if the id is out of range, otherwise like [`MeshBuilder::join`].
+    /// A mesh of a fixed size is only joined with the id of a peer.
+    pub fn join_with(&self, peer: usize) -> Result<Shard<T>, JoinError> {
+        self.join_with_options(peer, JoinOptions::default())
     }
 
-    pub fn with_cpu(nr_peers: usize, nb_cpu: usize) -> std::io::Result<Self> {
+    /// Join the mesh like [`MeshBuilder::join_with`], with some options.
+    pub fn join_with_options(
+        &self,
+        peer: usize,
+        options: JoinOptions,
+    ) -> Result<Shard<T>, JoinError> {
+        self.join_peer(peer, options)
+    }
+}
+
+impl<T, const N: usize> MeshBuilder<T, N> {
+    /// Create a new mesh between `N` peers.
+    ///
+    /// Its peers join it with their id, an index can't be out of range:
+    ///
+    /// ```compile_fail
+    /// use sharded_thread::mesh::MeshBuilder;
+    ///
+    /// let mesh = MeshBuilder::<usize, 2>::fixed_with_cpu(2).unwrap();
+    /// let out_of_range = mesh.join_with(2);
+    /// ```
+    pub fn fixed() -> std::io::Result<Self> {
+        let nb_cpu = std::thread::available_parallelism()?.get();
+
+        Self::fixed_with_cpu(nb_cpu)
+    }
+
+    /// Create a new mesh between `N` peers, like [`MeshBuilder::with_cpu`].
+    pub fn fixed_with_cpu(nb_cpu: usize) -> std::io::Result<Self> {
+        const { assert!(N > 0, "a fixed mesh needs at least one peer") };
+
+        Self::build(N, nb_cpu)
+    }
+
+    /// Get the id of the peer `I` of a mesh of `N` peers, checked at compile
+    /// time.
+    ///
+    /// ```compile_fail
+    /// use sharded_thread::mesh::MeshBuilder;
+    ///
+    /// let mesh = MeshBuilder::<usize, 2>::fixed_with_cpu(2).unwrap();
+    /// let out_of_range = mesh.id::<2>();
+    /// ```
+    pub fn id<const I: usize>(&self) -> ShardId {
+        const { assert!(I < N, "the peer is out of the mesh") };
+
+        self.membership
+            .peer_id(I)
+            .expect("the peer was checked at compile time")
+    }
+
+    /// Get the id of the peer at `index`, `None` if it is out of the mesh.
+    pub fn peer_id(&self, index: usize) -> Option<ShardId> {
+        self.membership.peer_id(index)
+    }
+
+    /// Get the id of every peer of the mesh.
+    pub fn peer_ids(&self) -> impl Iterator<Item = ShardId> + '_ {
+        (0..self.nr_peers).filter_map(|peer| self.membership.peer_id(peer))
+    }
+
+    /// Ids of the peers which are currently part of the mesh.
+    pub fn members(&self) -> Vec<usize> {
+        self.membership.members()
+    }
+
+    /// Number of peers which are currently part of the mesh.
+    pub fn nr_members(&self) -> usize {
+        self.membership.len()
+    }
+
+    /// Wait until every peer joined the mesh.
+    ///
+    /// Values sent to a peer which did not join yet are refused, wait for the
+    /// mesh to be ready to be sure every [`Shard`] can be reached. Once every
+    /// peer joined, the mesh stays ready even if some peers leave.
+    ///
+    /// Fail if the mesh is shut down before every peer joined it.
+    pub async fn ready(&self) -> Result<(), MeshClosed> {
+        self.membership.ready().await
+    }
+
+    /// Wait until every peer joined the mesh or until `delay` resolves, the
+    /// error reports the peers which are still missing.
+    ///
+    /// Fail early if the mesh is shut down before every peer joined it.
+    ///
+    /// The delay is a future so any runtime timer can be used, e.g.
+    /// `monoio::time::sleep(duration)`.
+    pub async fn ready_timeout<D>(&self, delay: D) -> Result<(), NotReady>
+    where
+        D: Future<Output = ()>,
+    {
+        self.membership.ready_timeout(delay).await
+    }
+
+    fn build(nr_peers: usize, nb_cpu: usize) -> std::io::Result<Self> {
+        // Keys couldn't be routed to any shard.
+        if nr_peers == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "a mesh needs at least one peer",
+            ));
+        }
+
         let mut channels = Vec::with_capacity(nr_peers);
+        let mut tasks = Vec::with_capacity(nr_peers);
 
         for _i in 0..nr_peers {
             channels.push(SharedQueueThreaded::<T>::new(nb_cpu)?);
+            tasks.push(SharedQueueThreaded::<Task>::new(nb_cpu)?);
         }
 
         Ok(Self {
             nr_peers,
+            nb_cpu,
+            capacity: None,
+            transport: Transport::default(),
+            priorities: 1,
+            #[cfg(target_os = "linux")]
+            eventfds: None,
             channels,
-            shared_joined: Arc::new(AtomicUsize::new(0)),
+            typed_channels: Channels::default(),
+            tasks,
+            routing: Routing::default(),
+            membership: Arc::new(Membership::new(nr_peers)),
         })
     }
 
-    /// Try to send an item directly to a shard, you must know the id of the
-    /// shard you want to send the item to.
+    /// Bound the number of items which can be waiting in the queue of each
+    /// shard.
+    ///
+    /// When the queue of a shard is full, [`Shard::try_send_to`] fails and
+    /// [`Shard::send_to_async`] waits until the receiving shard made some room.
+    /// With [`MeshBuilder::with_priorities`], each priority of the queue can
+    /// hold `capacity` items on its own.
+    ///
+    /// It must be configured while no peer is part of the mesh, it panics
+    /// otherwise.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self.rebuild_channels();
+        self
+    }
+
+    /// Select how values are carried between shards, see [`Transport`].
     ///
-    /// Fail if the shard is not registered.
+    /// It must be configured while no peer is part of the mesh, it panics
+    /// otherwise.
+    pub fn with_transport(mut self, transport: Transport) -> Self {
+        self.transport = transport;
+        self.rebuild_channels();
+        self
+    }
+
+    /// Guarantee that the values sent by a shard to another are received in
+    /// the order they were sent.
+    ///
+    /// Without it, two values sent by a shard to another one can be received
+    /// in any order. The guarantee holds for every way a shard sends values,
+    /// including batches, sinks and broadcasts, but not for the values sent
+    /// directly through the mesh. It uses the [`Transport::Rings`] transport,
+    /// so each pair of shards is bounded by the capacity of the mesh.
+    ///
+    /// It must be configured while no peer is part of the mesh, it panics
+    /// otherwise.
+    pub fn with_fifo_per_sender(self) -> Self {
+        self.with_transport(Transport::Rings)
+    }
+
+    /// Whether the values sent by a shard to another are received in order,
+    /// see [`MeshBuilder::with_fifo_per_sender`].
+    pub fn is_fifo_per_sender(&self) -> bool {
+        self.transport == Transport::Rings
+    }
+
+    /// Give the queue of each shard `priorities` levels, values sent with
+    /// [`Shard::send_to_with_priority`] are received before the values sent
+    /// with a lower priority.
+    ///
+    /// To avoid starving them, a value with a lower priority is received once
+    /// 32 values with a higher priority were received while it was waiting.
+    /// The values sent with the same priority are only received in order when
+    /// the mesh guarantees it, see [`MeshBuilder::with_fifo_per_sender`].
+    ///
+    /// It must be configured while no peer is part of the mesh, it panics
+    /// otherwise.
+    pub fn with_priorities(mut self, priorities: usize) -> Self {
+        self.priorities = priorities.max(1);
+        self.rebuild_channels();
+        self
+    }
+
+    /// Give each shard an eventfd written when values are sent to it, see
+    /// [`EventFd`].
+    ///
+    /// The shard gets it from its receiver with [`Receiver::eventfd`], to wait
+    /// for values through its io_uring ring or epoll. It must be configured
+    /// while no peer is part of the mesh, it panics otherwise.
+    ///
+    /// [`Receiver::eventfd`]: crate::Receiver::eventfd
+    #[cfg(target_os = "linux")]
+    pub fn with_eventfd(mut self) -> std::io::Result<Self> {
+        self.assert_no_member();
+        self.eventfds = Some(
+            (0..self.nr_peers)
+                .map(|_| EventFd::new().map(Arc::new))
+                .collect::<std::io::Result<_>>()?,
+        );
+        self.rebuild_channels();
+        Ok(self)
+    }
+
+    /// Add a channel carrying values of type `U` between the peers, along with
+    /// the values of the mesh and its other channels.
+    ///
+    /// Every channel shares the peers of the mesh, a [`Shard`] reaches the
+    /// channel with [`Shard::channel`]. The queues of the channel are
+    /// configured like the ones of the mesh.
+    ///
+    /// It must be added while no peer is part of the mesh, it panics
+    /// otherwise.
+    pub fn with_channel<U: Send + 'static>(mut self) -> Self {
+        self.assert_no_member();
+
+        let mut typed_channels = std::mem::take(&mut self.typed_channels);
+        typed_channels.register::<U>(
+            self.nb_cpu,
+            |peer| self.queue_options(peer),
+            self.nr_peers,
+        );
+        self.typed_channels = typed_channels;
+        self
+    }
+
+    /// Route keys to shards with `router`, used by [`Shard::send_by_key`].
+    ///
+    /// Keys are routed with [`Modulo`](crate::router::Modulo) by default. It
+    /// must be configured while no peer is part of the mesh, it panics
+    /// otherwise.
+    pub fn with_router<R: Router>(mut self, router: R) -> Self {
+        self.assert_no_member();
+        self.routing = self.routing.with_router(router);
+        self
+    }
+
+    /// Hash keys with `hasher` before routing them to shards.
+    ///
+    /// The hasher must build the same hasher for every shard, the
+    /// `DefaultHasher` of the standard library is used by default. It must be
+    /// configured while no peer is part of the mesh, it panics otherwise.
+    pub fn with_hasher<S>(mut self, hasher: S) -> Self
+    where
+        S: BuildHasher + Send + Sync + 'static,
+    {
+        self.assert_no_member();
+        self.routing = self.routing.with_hasher(hasher);
+        self
+    }
+
+    /// Get the shard owning `key`, without sending anything.
+    pub fn shard_for_key<K: Hash + ?Sized>(&self, key: &K) -> usize {
+        self.routing.shard_for_key(key, self.nr_peers)
+    }
+
+    /// Panic if a peer is part of the mesh: its shard would keep using the
+    /// previous configuration.
+    fn assert_no_member(&self) {
+        assert_eq!(
+            self.membership.len(),
+            0,
+            "the mesh must be configured before any peer joins it"
+        );
+    }
+
+    /// Create the queues of every peer from the configuration of the mesh.
+    fn rebuild_channels(&mut self) {
+        self.assert_no_member();
+
+        self.channels = (0..self.nr_peers)
+            .map(|peer| {
+                SharedQueueThreaded::<T>::with_options(
+                    self.nb_cpu,
+                    self.queue_options(peer),
+                )
+            })
+            .collect();
+
+        let mut typed_channels = std::mem::take(&mut self.typed_channels);
+        typed_channels.rebuild(
+            self.nb_cpu,
+            &|peer| self.queue_options(peer),
+            self.nr_peers,
+        );
+        self.typed_channels = typed_channels;
+
+        self.tasks = (0..self.nr_peers)
+            .map(|peer| {
+                SharedQueueThreaded::<Task>::with_options(
+                    self.nb_cpu,
+                    self.task_options(peer),
+                )
+            })
+            .collect();
+    }
+
+    /// Options of the task queue of `peer`, unbounded but notifying the same
+    /// eventfd as its channels.
+    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+    fn task_options(&self, peer: usize) -> QueueOptions {
+        QueueOptions {
+            #[cfg(target_os = "linux")]
+            eventfd: self.queue_options(peer).eventfd,
+            ..QueueOptions::default()
+        }
+    }
+
+    /// Options of the queues of `peer` in every channel.
+    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+    fn queue_options(&self, peer: usize) -> QueueOptions {
+        QueueOptions {
+            capacity: self.capacity,
+            rings: match self.transport {
+                Transport::Sharded => None,
+                Transport::Rings => Some(self.nr_peers),
+            },
+            priorities: self.priorities,
+            #[cfg(target_os = "linux")]
+            eventfd: self
+                .eventfds
+                .as_ref()
+                .map(|eventfds| eventfds[peer].clone()),
+        }
+    }
+
+    /// Try to send an item directly to the shard with the id `shard`.
+    ///
+    /// Fail if the id belongs to another mesh or if the shard is closed, the
+    /// item is given back.
     #[doc(hidden)]
-    pub fn send_to(&self, pos: usize, item: T) -> Result<(), SenderError> {
-        self.channels
-            .get(pos)
-            .ok_or(SenderError::WrongShard)?
-            .sender()
-            .send(item);
-        Ok(())
+    pub fn send_to(&self, item: T, shard: ShardId) -> Result<(), SendError<T>> {
+        let Some(channel) = self
+            .membership
+            .peer_of(shard)
+            .and_then(|peer| self.channels.get(peer))
+        else {
+            return Err(SendError::WrongShard(item));
+        };
+
+        channel.sender().send(item)
     }
 
-    /// Join the mesh means you can talk to other peer and peer can talk to you.
+    /// Create a barrier every peer of the mesh can wait on, see
+    /// [`ShardBarrier`].
+    pub fn barrier(&self) -> ShardBarrier {
+        ShardBarrier::new(self.channels.len(), self.membership.clone())
+    }
+
+    /// Shutdown the mesh by closing every shard.
     ///
-    /// You must assign yourself an id so other Shard will be able to talk with
-    /// you using this ID
-    pub fn join_with(&self, peer: usize) -> std::io::Result<Shard<T>> {
-        self.shared_joined
-            .fetch_add(1, std::sync::atomic::Ordering::Acquire);
+    /// Values can't be sent anymore and the receiver of each shard ends once
+    /// it received every value which was already sent to it. The tasks waiting
+    /// for every peer to join are woken and fail.
+    pub fn shutdown(&self) {
+        self.membership.close();
+        for channel in &self.channels {
+            channel.close();
+        }
+        self.typed_channels.close();
+        for tasks in &self.tasks {
+            tasks.close();
+        }
+    }
+
+    /// Open the queues of `peer` again, the previous [`Shard`] with the same id
+    /// may have closed them before leaving.
+    fn reopen(&self, peer: usize) {
+        self.channels[peer].reopen();
+        self.typed_channels.reopen(peer);
+        self.tasks[peer].reopen();
 
-        assert!(peer < self.channels.len());
+        // The mesh may have been shut down since the peer joined, which
+        // closed the queues before they were opened again.
+        if self.membership.is_closed() {
+            self.shutdown();
+        }
+    }
+
+    /// Join the mesh with the id `id`, the other shards can send values to
+    /// the [`Shard`] with this id.
+    ///
+    /// Fail with [`JoinError::WrongMesh`] if the id belongs to another mesh,
+    /// if the mesh is shut down or if a [`Shard`] with the same id is already
+    /// part of the mesh. A peer can join again with the same id once the
+    /// previous [`Shard`] left, values sent to it in the meantime are kept for
+    /// the new [`Shard`].
+    pub fn join(&self, id: ShardId) -> Result<Shard<T>, JoinError> {
+        self.join_id_with_options(id, JoinOptions::default())
+    }
+
+    /// Join the mesh like [`MeshBuilder::join`], with some options.
+    pub fn join_id_with_options(
+        &self,
+        id: ShardId,
+        options: JoinOptions,
+    ) -> Result<Shard<T>, JoinError> {
+        let peer = self.membership.peer_of(id).ok_or(JoinError::WrongMesh)?;
+        self.join_peer(peer, options)
+    }
+
+    /// Join the mesh as `peer`, fail if it is out of range.
+    fn join_peer(
+        &self,
+        peer: usize,
+        options: JoinOptions,
+    ) -> Result<Shard<T>, JoinError> {
+        self.membership.join(peer)?;
+        Ok(self.shard(peer, options))
+    }
+
+    /// Join the mesh with the first id which is not used by another [`Shard`].
+    ///
+    /// Fail if every peer already joined or if the mesh is shut down.
+    pub fn join_next(&self) -> Result<Shard<T>, JoinError> {
+        self.join_next_with_options(JoinOptions::default())
+    }
+
+    /// Join the mesh like [`MeshBuilder::join_next`], with some options.
+    pub fn join_next_with_options(
+        &self,
+        options: JoinOptions,
+    ) -> Result<Shard<T>, JoinError> {
+        let peer = self.membership.join_next()?;
+        Ok(self.shard(peer, options))
+    }
+
+    /// Create the [`Shard`] of a peer which just joined the mesh.
+    fn shard(&self, peer: usize, options: JoinOptions) -> Shard<T> {
+        self.reopen(peer);
+
+        if options.discard_pending {
+            self.channels[peer].clear();
+            self.typed_channels.clear(peer);
+            self.tasks[peer].clear();
+        }
 
         let senders = self
             .channels
             .iter()
-            .map(SharedQueueChannels::sender)
+            .map(|channel| shard_sender(channel, peer))
             .collect();
         let (_, receiver) = self.channels[peer].unbounded();
+        let receiver = receiver.with_recv_mode(options.recv_mode);
+
+        let task_senders =
+            self.tasks.iter().map(SharedQueueChannels::sender).collect();
+        let (_, remote_tasks) = self.tasks[peer].unbounded();
 
-        Ok(Shard {
+        Shard {
             receiver: Cell::new(Some(receiver)),
             senders,
-            max_shard: self.shared_joined.clone(),
+            channels: self.typed_channels.ends(peer),
+            remote_tasks: Cell::new(Some(remote_tasks)),
+            task_senders,
+            routing: self.routing.clone(),
+            membership: self.membership.clone(),
             shard_id: peer,
-        })
+            not_sync: PhantomData,
+        }
     }
 }
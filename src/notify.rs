@@ -0,0 +1,124 @@
+//! Notify a shard through an `eventfd` when values are sent to it.
+//!
+//! The shard can register the file descriptor with its io_uring ring or with
+//! epoll, it becomes readable when some values are waiting in the queue of the
+//! shard. Multiple sends are coalesced into one notification until the shard
+//! clears it.
+
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// An `eventfd` owned by a shard and written by the shards sending values to
+/// it.
+///
+/// Once it is readable, call [`EventFd::clear`] before receiving the values
+/// waiting in the queue, values sent afterwards notify the shard again.
+pub struct EventFd {
+    fd: OwnedFd,
+    /// Set once the eventfd was written and until it is cleared, so multiple
+    /// sends only write it once.
+    notified: AtomicBool,
+}
+
+impl std::fmt::Debug for EventFd {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventFd").field("fd", &self.fd).finish()
+    }
+}
+
+impl EventFd {
+    pub(crate) fn new() -> io::Result<Self> {
+        // SAFETY: eventfd has no memory safety requirement.
+        let fd =
+            unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            // SAFETY: the file descriptor was just created and is owned here.
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            notified: AtomicBool::new(false),
+        })
+    }
+
+    /// Make the eventfd readable, unless it is already.
+    pub(crate) fn notify(&self) {
+        if self.notified.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        let val: u64 = 1;
+        // SAFETY: the buffer is a valid u64, as eventfd expects. The write can
+        // only fail if the counter overflows, it is readable then anyway.
+        unsafe {
+            libc::write(
+                self.fd.as_raw_fd(),
+                &val as *const u64 as *const libc::c_void,
+                std::mem::size_of::<u64>(),
+            );
+        }
+    }
+
+    /// Acknowledge the notification, the eventfd is not readable anymore until
+    /// new values are sent.
+    ///
+    /// Return the number of notifications which were coalesced, `0` if the
+    /// eventfd was not readable.
+    pub fn clear(&self) -> u64 {
+        let mut val: u64 = 0;
+        // SAFETY: the buffer is a valid u64, as eventfd expects. The eventfd
+        // is non blocking so the read fails if it was not readable.
+        let read = unsafe {
+            libc::read(
+                self.fd.as_raw_fd(),
+                &mut val as *mut u64 as *mut libc::c_void,
+                std::mem::size_of::<u64>(),
+            )
+        };
+
+        // Values sent before this point are visible to the shard, the next
+        // ones write the eventfd again.
+        self.notified.swap(false, Ordering::AcqRel);
+
+        if read < 0 {
+            0
+        } else {
+            val
+        }
+    }
+}
+
+impl AsRawFd for EventFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl AsFd for EventFd {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EventFd;
+
+    #[test]
+    fn ensure_notifications_are_coalesced() {
+        let eventfd = EventFd::new().unwrap();
+
+        assert_eq!(eventfd.clear(), 0);
+
+        eventfd.notify();
+        eventfd.notify();
+        eventfd.notify();
+        assert_eq!(eventfd.clear(), 1);
+        assert_eq!(eventfd.clear(), 0);
+
+        eventfd.notify();
+        assert_eq!(eventfd.clear(), 1);
+    }
+}
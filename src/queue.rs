@@ -1,16 +1,107 @@
-use std::sync::atomic::AtomicUsize;
-use std::sync::Arc;
-use std::task::Poll;
+//! Multi-producer single-consumer queues connecting the shards of a mesh.
+
+use std::collections::VecDeque;
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicBool, AtomicUsize};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::Thread;
+use std::time::{Duration, Instant};
 
 use futures::task::AtomicWaker;
 use futures::Stream;
 use sharded_queue::ShardedQueue;
 
+#[cfg(target_os = "linux")]
+use crate::notify::EventFd;
+use crate::ring::SpscRing;
+use crate::shard::SendError;
+
+/// Bit of `task_queue` set once the queue is closed, the other bits are the
+/// number of items in the queue.
+const CLOSED: usize = 1 << (usize::BITS - 1);
+
+/// Capacity of each ring of a queue using rings when no capacity is given.
+const DEFAULT_RING_CAPACITY: usize = 1024;
+
+/// Number of items a receiver pops from the higher priorities of a queue while
+/// a lower priority has some items waiting, before it pops one of them.
+const STARVATION_LIMIT: usize = 32;
+
 /// A queue that should be available on each thread.
 pub struct SharedQueueThreaded<T> {
-    queue: ShardedQueue<T>,
+    /// The items sent with each priority, from the lowest to the highest.
+    levels: Box<[Level<T>]>,
     task_queue: AtomicUsize,
+    /// Maximum number of items waiting in the queue, `None` when the queue is
+    /// unbounded. When the queue has several levels, it is the capacity of
+    /// each level. When the queue uses rings, it is the capacity of each ring.
+    capacity: Option<usize>,
     waker: AtomicWaker,
+    /// Senders waiting for the receiver to make some room in a bounded queue.
+    senders_waker: Mutex<Vec<Waker>>,
+    /// Set while some senders are waiting, so the receiver only takes the lock
+    /// of `senders_waker` when it has someone to wake.
+    senders_waiting: AtomicBool,
+    /// Bumped when the shard owning the queue leaves the mesh, the receivers
+    /// created before end then.
+    epoch: AtomicUsize,
+    /// Notified along with the waker when items are sent.
+    #[cfg(target_os = "linux")]
+    eventfd: Option<Arc<EventFd>>,
+}
+
+/// The items of a queue sent with the same priority.
+struct Level<T> {
+    storage: Storage<T>,
+    /// Number of items pushed to this level and not popped yet, only counted
+    /// when the queue has several levels.
+    len: AtomicUsize,
+    /// Number of slots reserved in this level and not popped yet, only counted
+    /// when the queue has several levels.
+    reserved: AtomicUsize,
+    /// Number of items popped from a higher level in a row while this one had
+    /// some items waiting.
+    skipped: AtomicUsize,
+}
+
+/// Where the items of a queue are stored.
+enum Storage<T> {
+    /// One queue shared by every sender.
+    Sharded(Box<ShardedQueue<T>>),
+    /// One ring per sending shard, popped round-robin, and a lane for the
+    /// senders which are not a shard.
+    Rings {
+        rings: Box<[SpscRing<T>]>,
+        external: Mutex<VecDeque<T>>,
+        /// Next lane to pop from, it also prevents two receivers from popping
+        /// the same ring at once.
+        cursor: Mutex<usize>,
+    },
+}
+
+/// Options used to create a [`SharedQueueThreaded`].
+#[derive(Default)]
+pub(crate) struct QueueOptions {
+    pub(crate) capacity: Option<usize>,
+    /// Number of sending shards when the queue uses one ring per sending
+    /// shard.
+    pub(crate) rings: Option<usize>,
+    /// Number of priorities, at least one.
+    pub(crate) priorities: usize,
+    #[cfg(target_os = "linux")]
+    pub(crate) eventfd: Option<Arc<EventFd>>,
+}
+
+/// Which part of the queue a sender pushes its items to.
+#[derive(Debug, Clone, Copy)]
+enum Lane {
+    /// The queue shared by every sender.
+    Shared,
+    /// The ring of a sending shard.
+    Ring(usize),
+    /// The lane of the senders which are not a shard.
+    External,
 }
 
 impl<T> SharedQueueThreaded<T> {
@@ -20,15 +111,483 @@ impl<T> SharedQueueThreaded<T> {
     pub fn new(
         max_concurrent_thread_count: usize,
     ) -> std::io::Result<Arc<Self>> {
-        let waker = AtomicWaker::new();
-        Ok(Arc::new(Self {
-            queue: ShardedQueue::new(max_concurrent_thread_count),
+        Ok(Self::with_capacity(max_concurrent_thread_count, None))
+    }
+
+    /// Create a new `SharedQueueThreaded` which can hold at most `capacity`
+    /// items when a capacity is given.
+    pub(crate) fn with_capacity(
+        max_concurrent_thread_count: usize,
+        capacity: Option<usize>,
+    ) -> Arc<Self> {
+        Self::with_options(
+            max_concurrent_thread_count,
+            QueueOptions {
+                capacity,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Create a new `SharedQueueThreaded` with some options.
+    pub(crate) fn with_options(
+        max_concurrent_thread_count: usize,
+        options: QueueOptions,
+    ) -> Arc<Self> {
+        let levels = (0..options.priorities.max(1))
+            .map(|_| Level {
+                storage: Storage::new(max_concurrent_thread_count, &options),
+                len: AtomicUsize::new(0),
+                reserved: AtomicUsize::new(0),
+                skipped: AtomicUsize::new(0),
+            })
+            .collect();
+
+        Arc::new(Self {
+            levels,
             task_queue: AtomicUsize::new(0),
-            waker,
-        }))
+            capacity: options.capacity,
+            waker: AtomicWaker::new(),
+            senders_waker: Mutex::new(Vec::new()),
+            senders_waiting: AtomicBool::new(false),
+            epoch: AtomicUsize::new(0),
+            #[cfg(target_os = "linux")]
+            eventfd: options.eventfd,
+        })
+    }
+
+    /// Get the lane used by the sender of shard `source`, or by a sender which
+    /// is not a shard.
+    fn lane(&self, source: Option<usize>) -> Lane {
+        match (&self.levels[0].storage, source) {
+            (Storage::Sharded(_), _) => Lane::Shared,
+            (Storage::Rings { rings, .. }, Some(source))
+                if source < rings.len() =>
+            {
+                Lane::Ring(source)
+            }
+            (Storage::Rings { .. }, _) => Lane::External,
+        }
+    }
+
+    /// Get the level of the items sent with `priority`, priorities above the
+    /// highest one are sent with the highest one.
+    fn level(&self, priority: usize) -> &Level<T> {
+        &self.levels[priority.min(self.levels.len() - 1)]
+    }
+
+    /// Wake the receiver once items were pushed.
+    fn notify(&self) {
+        self.waker.wake();
+
+        #[cfg(target_os = "linux")]
+        if let Some(eventfd) = &self.eventfd {
+            eventfd.notify();
+        }
+    }
+
+    /// Reserve `count` slots at once, fail if the queue doesn't have room for
+    /// all of them or if it is closed.
+    ///
+    /// When `bounded` is false the capacity of the queue is ignored, rings
+    /// can't hold more than their capacity though.
+    fn try_reserve_many(
+        &self,
+        lane: Lane,
+        priority: usize,
+        count: usize,
+        bounded: bool,
+    ) -> Result<(), Rejected> {
+        self.try_reserve(lane, priority, count..=count, bounded)
+            .map(|_| ())
+    }
+
+    /// Reserve up to `max` slots at once, return how many were reserved.
+    ///
+    /// Fail if the queue is full or closed.
+    fn try_reserve_up_to(
+        &self,
+        lane: Lane,
+        priority: usize,
+        max: usize,
+    ) -> Result<usize, Rejected> {
+        self.try_reserve(lane, priority, 1..=max, true)
+    }
+
+    /// Reserve as many slots as possible in `range`, fail if there is not
+    /// enough room for the start of the range.
+    ///
+    /// The items are counted as soon as their slot is reserved, in the same
+    /// atomic operation which checks that the queue is not closed: once the
+    /// queue is closed no item can be sent anymore and the receiver waits for
+    /// the items already reserved.
+    ///
+    /// Ring lanes are counted there too, so the senders of a queue with rings
+    /// still update one shared atomic even though they push to their own ring.
+    /// A count per ring would avoid it, but closing the queue and receiving
+    /// would then have to go through every ring instead of a single atomic
+    /// operation. The `fan_in` benches measure what this shared count costs.
+    fn try_reserve(
+        &self,
+        lane: Lane,
+        priority: usize,
+        range: RangeInclusive<usize>,
+        bounded: bool,
+    ) -> Result<usize, Rejected> {
+        let (min, mut max) = range.into_inner();
+        if self.is_closed() {
+            return Err(Rejected::Closed);
+        }
+
+        let capacity = match lane {
+            Lane::Shared if bounded => self.capacity.unwrap_or(!CLOSED),
+            Lane::Shared | Lane::External => !CLOSED,
+            Lane::Ring(source) => {
+                let Storage::Rings { rings, .. } =
+                    &self.level(priority).storage
+                else {
+                    unreachable!("a ring lane is only used with rings");
+                };
+
+                // The ring bounds its lane, only its own sender pushes to it
+                // so its free slots can't be taken meanwhile.
+                let free = rings[source].free();
+                if free < min {
+                    return Err(Rejected::Full);
+                }
+                max = free.min(max);
+                !CLOSED
+            }
+        };
+
+        if self.levels.len() == 1 {
+            return reserve_in(&self.task_queue, capacity, min..=max);
+        }
+
+        // Each level has its own capacity, the slots are reserved in the level
+        // first and the queue only checks that it is not closed.
+        let level = self.level(priority);
+        let reserved = reserve_in(&level.reserved, capacity, min..=max)?;
+        if let Err(rejected) =
+            reserve_in(&self.task_queue, !CLOSED, reserved..=reserved)
+        {
+            level
+                .reserved
+                .fetch_sub(reserved, std::sync::atomic::Ordering::AcqRel);
+            return Err(rejected);
+        }
+
+        Ok(reserved)
+    }
+
+    /// Push an item for which a slot was reserved in `lane`.
+    fn push_reserved(&self, lane: Lane, priority: usize, item: T) {
+        match (&self.level(priority).storage, lane) {
+            (Storage::Sharded(queue), _) => queue.push_back(item),
+            (Storage::Rings { rings, .. }, Lane::Ring(source)) => {
+                // SAFETY: a ring is only pushed to by the sender of its
+                // source shard, which is only reachable through a `&Shard`
+                // and `Shard` is explicitly not `Sync`. The slot was reserved
+                // so the push can't fail.
+                let pushed = unsafe { rings[source].push(item) };
+                debug_assert!(pushed.is_ok(), "the slot should be reserved");
+            }
+            (Storage::Rings { external, .. }, _) => external
+                .lock()
+                .expect("the external lane lock should not be poisoned")
+                .push_back(item),
+        }
+    }
+
+    /// Make `count` pushed items visible to the receiver and wake it.
+    fn commit(&self, priority: usize, count: usize) {
+        // A single level is never picked among others, it is not counted.
+        if self.levels.len() > 1 {
+            self.level(priority)
+                .len
+                .fetch_add(count, std::sync::atomic::Ordering::AcqRel);
+        }
+
+        self.notify();
+    }
+
+    /// Pop an item which was counted in the length of the queue.
+    fn pop_counted(&self) -> T {
+        // Its storage waits for the item to be pushed on its own.
+        if let [level] = &*self.levels {
+            return level.storage.pop();
+        }
+
+        loop {
+            if let Some(level) = self.next_level() {
+                return level.storage.pop();
+            }
+
+            // The item is counted but not pushed yet.
+            std::hint::spin_loop();
+        }
+    }
+
+    /// Pick the level to pop the next item from and count the item out of it,
+    /// `None` if no item was pushed yet.
+    ///
+    /// The highest level with some items waiting is picked, unless a lower one
+    /// was skipped [`STARVATION_LIMIT`] times: the most skipped one is picked
+    /// then.
+    fn next_level(&self) -> Option<&Level<T>> {
+        let waiting = |level: &Level<T>| {
+            level.len.load(std::sync::atomic::Ordering::Acquire) > 0
+        };
+        let skipped = |level: &Level<T>| {
+            level.skipped.load(std::sync::atomic::Ordering::Relaxed)
+        };
+
+        let highest = self.levels.iter().rposition(waiting)?;
+        let starved = self.levels[..highest]
+            .iter()
+            .enumerate()
+            .filter(|(_, level)| {
+                waiting(level) && skipped(level) >= STARVATION_LIMIT
+            })
+            .max_by_key(|(_, level)| skipped(level))
+            .map(|(index, _)| index);
+        let picked = starved.unwrap_or(highest);
+
+        let level = &self.levels[picked];
+        update_len(&level.len, |len| len.checked_sub(1))?;
+        level
+            .reserved
+            .fetch_sub(1, std::sync::atomic::Ordering::AcqRel);
+        level.skipped.store(0, std::sync::atomic::Ordering::Relaxed);
+
+        for (index, other) in self.levels[..highest].iter().enumerate() {
+            if index != picked && waiting(other) {
+                other
+                    .skipped
+                    .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            }
+        }
+
+        Some(level)
+    }
+
+    /// Wake `waker` once the receiver made some room in the queue.
+    fn register_sender(&self, waker: &Waker) {
+        let mut wakers = self
+            .senders_waker
+            .lock()
+            .expect("the senders waker lock should not be poisoned");
+        if !wakers.iter().any(|registered| registered.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+        self.senders_waiting
+            .store(true, std::sync::atomic::Ordering::SeqCst);
+
+        // The sender checks the queue again after registering, which must not
+        // be reordered before the flag is set.
+        std::sync::atomic::fence(std::sync::atomic::Ordering::SeqCst);
+    }
+
+    /// Close the queue: no more items can be sent and the receiver will stop
+    /// once every remaining item is received.
+    pub(crate) fn close(&self) {
+        self.task_queue
+            .fetch_or(CLOSED, std::sync::atomic::Ordering::AcqRel);
+        self.notify();
+        self.wake_senders();
+    }
+
+    /// Open the queue again after it was closed, items can be sent again.
+    pub(crate) fn reopen(&self) {
+        self.task_queue
+            .fetch_and(!CLOSED, std::sync::atomic::Ordering::AcqRel);
+    }
+
+    /// Drop every item waiting in the queue, return how many were dropped.
+    pub(crate) fn clear(&self) -> usize {
+        let mut cleared = 0;
+        while update_len(&self.task_queue, |len| {
+            (len & !CLOSED > 0).then(|| len - 1)
+        })
+        .is_some()
+        {
+            drop(self.pop_counted());
+            cleared += 1;
+        }
+
+        self.wake_senders();
+        cleared
+    }
+
+    /// End the receivers created until now, the values stay in the queue for
+    /// the receivers created afterwards.
+    pub(crate) fn retire_receivers(&self) {
+        self.epoch.fetch_add(1, std::sync::atomic::Ordering::AcqRel);
+        self.notify();
+    }
+
+    /// Check if the queue is closed.
+    pub(crate) fn is_closed(&self) -> bool {
+        self.task_queue.load(std::sync::atomic::Ordering::Acquire) & CLOSED != 0
+    }
+
+    /// Wake every sender waiting for some room in the queue.
+    fn wake_senders(&self) {
+        // The room was made before, it must not be reordered after the flag
+        // is checked.
+        std::sync::atomic::fence(std::sync::atomic::Ordering::SeqCst);
+        if !self
+            .senders_waiting
+            .load(std::sync::atomic::Ordering::SeqCst)
+        {
+            return;
+        }
+
+        let wakers = {
+            let mut wakers = self
+                .senders_waker
+                .lock()
+                .expect("the senders waker lock should not be poisoned");
+            self.senders_waiting
+                .store(false, std::sync::atomic::Ordering::SeqCst);
+            std::mem::take(&mut *wakers)
+        };
+        for waker in wakers {
+            waker.wake();
+        }
     }
 }
 
+impl<T> Storage<T> {
+    fn new(max_concurrent_thread_count: usize, options: &QueueOptions) -> Self {
+        match options.rings {
+            Some(rings) => Storage::Rings {
+                rings: (0..rings)
+                    .map(|_| {
+                        SpscRing::new(
+                            options.capacity.unwrap_or(DEFAULT_RING_CAPACITY),
+                        )
+                    })
+                    .collect(),
+                external: Mutex::new(VecDeque::new()),
+                cursor: Mutex::new(0),
+            },
+            None => Storage::Sharded(Box::new(ShardedQueue::new(
+                max_concurrent_thread_count,
+            ))),
+        }
+    }
+
+    /// Pop an item which was pushed, the rings are popped round-robin.
+    fn pop(&self) -> T {
+        let (rings, external, cursor) = match self {
+            Storage::Sharded(queue) => {
+                return queue.pop_front_or_spin_wait_item()
+            }
+            Storage::Rings {
+                rings,
+                external,
+                cursor,
+            } => (rings, external, cursor),
+        };
+
+        let mut cursor = cursor
+            .lock()
+            .expect("the cursor lock should not be poisoned");
+        let lanes = rings.len() + 1;
+
+        loop {
+            for offset in 0..lanes {
+                let lane = (*cursor + offset) % lanes;
+                let item = match rings.get(lane) {
+                    // SAFETY: rings are only popped while holding the cursor.
+                    Some(ring) => unsafe { ring.pop() },
+                    None => external
+                        .lock()
+                        .expect("the external lane lock should not be poisoned")
+                        .pop_front(),
+                };
+
+                if let Some(item) = item {
+                    *cursor = lane + 1;
+                    return item;
+                }
+            }
+
+            std::hint::spin_loop();
+        }
+    }
+}
+
+/// Update the length of a queue with `f` until it succeeds, return the
+/// previous length or `None` if `f` refused the update.
+fn update_len(
+    len: &AtomicUsize,
+    mut f: impl FnMut(usize) -> Option<usize>,
+) -> Option<usize> {
+    let mut current = len.load(std::sync::atomic::Ordering::Acquire);
+    loop {
+        let next = f(current)?;
+        match len.compare_exchange_weak(
+            current,
+            next,
+            std::sync::atomic::Ordering::AcqRel,
+            std::sync::atomic::Ordering::Acquire,
+        ) {
+            Ok(previous) => return Some(previous),
+            Err(actual) => current = actual,
+        }
+    }
+}
+
+/// Reserve as many slots of `len` as possible in `range`, without going over
+/// `capacity`, return how many were reserved.
+///
+/// Fail if there is not enough room for the start of the range or if `len` is
+/// closed.
+fn reserve_in(
+    len: &AtomicUsize,
+    capacity: usize,
+    range: RangeInclusive<usize>,
+) -> Result<usize, Rejected> {
+    let (min, max) = range.into_inner();
+    let mut rejected = Rejected::Closed;
+    let mut reserved = 0;
+
+    update_len(len, |len| {
+        let room = capacity.saturating_sub(len);
+        reserved = room.min(max);
+        if len & CLOSED != 0 {
+            rejected = Rejected::Closed;
+            None
+        } else if room < min {
+            rejected = Rejected::Full;
+            None
+        } else {
+            Some(len + reserved)
+        }
+    })
+    .map(|_| reserved)
+    .ok_or(rejected)
+}
+
+/// Reason why a slot couldn't be reserved in a queue.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Rejected {
+    Full,
+    Closed,
+}
+
+impl Rejected {
+    pub(crate) fn with<T>(self, item: T) -> SendError<T> {
+        match self {
+            Rejected::Full => SendError::Full(item),
+            Rejected::Closed => SendError::Closed(item),
+        }
+    }
+}
+
+/// Create the ends of a queue.
 pub trait SharedQueueChannels<T> {
     fn unbounded(&self) -> (Sender<T>, Receiver<T>);
 
@@ -41,6 +600,10 @@ impl<T> SharedQueueChannels<T> for Arc<SharedQueueThreaded<T>> {
 
         let rx = Receiver {
             queue: Arc::clone(self),
+            epoch: self.epoch.load(std::sync::atomic::Ordering::Acquire),
+            mode: RecvMode::default(),
+            last_received: None,
+            average_gap: Duration::ZERO,
         };
 
         (tx, rx)
@@ -48,53 +611,476 @@ impl<T> SharedQueueChannels<T> for Arc<SharedQueueThreaded<T>> {
 
     fn sender(&self) -> Sender<T> {
         Sender {
+            lane: self.lane(None),
             queue: Arc::clone(self),
         }
     }
 }
 
+/// Create the sender used by the shard `source` to send items to `queue`.
+///
+/// Only one sender can exist for each source at a time, it pushes to the ring
+/// of its source when the queue uses rings.
+pub(crate) fn shard_sender<T>(
+    queue: &Arc<SharedQueueThreaded<T>>,
+    source: usize,
+) -> Sender<T> {
+    Sender {
+        lane: queue.lane(Some(source)),
+        queue: Arc::clone(queue),
+    }
+}
+
+/// Sending end of a queue.
 pub struct Sender<T> {
     queue: Arc<SharedQueueThreaded<T>>,
+    lane: Lane,
 }
 
 impl<T> Sender<T> {
     /// Attempts to send a value to the queue
-    pub fn send(&self, item: T) {
-        self.queue
-            .task_queue
-            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-        self.queue.queue.push_back(item);
-        self.queue.waker.wake();
+    ///
+    /// The capacity of the queue is not checked, fail only if the queue is
+    /// closed. When the queue uses rings, it fails if the ring of the sender
+    /// is full too.
+    pub fn send(&self, item: T) -> Result<(), SendError<T>> {
+        if let Err(rejected) =
+            self.queue.try_reserve_many(self.lane, 0, 1, false)
+        {
+            return Err(rejected.with(item));
+        }
+
+        self.push(0, item);
+        Ok(())
+    }
+
+    /// Attempts to send a value to the queue, give the value back if the queue
+    /// is full or closed.
+    pub fn try_send(&self, item: T) -> Result<(), SendError<T>> {
+        self.try_send_with_priority(item, 0)
+    }
+
+    /// Attempts to send a value to the queue with a priority, give the value
+    /// back if the queue is full or closed.
+    ///
+    /// The values with a higher priority are received first, `0` is the lowest
+    /// priority and the one used by the other methods. Priorities above the
+    /// highest priority of the queue are sent with the highest one.
+    pub fn try_send_with_priority(
+        &self,
+        item: T,
+        priority: usize,
+    ) -> Result<(), SendError<T>> {
+        if let Err(rejected) =
+            self.queue.try_reserve_many(self.lane, priority, 1, true)
+        {
+            return Err(rejected.with(item));
+        }
+
+        self.push(priority, item);
+        Ok(())
+    }
+
+    /// Attempts to send every value of `items` to the queue, the receiver is
+    /// woken up once for the whole batch.
+    ///
+    /// Either every value is sent or none: the values are given back if the
+    /// queue doesn't have room for all of them or if it is closed.
+    pub fn try_send_batch(
+        &self,
+        items: Vec<T>,
+    ) -> Result<(), SendError<Vec<T>>> {
+        if items.is_empty() {
+            return Ok(());
+        }
+
+        let count = items.len();
+        if let Err(rejected) =
+            self.queue.try_reserve_many(self.lane, 0, count, true)
+        {
+            return Err(rejected.with(items));
+        }
+
+        for item in items {
+            self.queue.push_reserved(self.lane, 0, item);
+        }
+        self.queue.commit(0, count);
+        Ok(())
+    }
+
+    /// Send a value to the queue, waiting for the receiver to make some room
+    /// if the queue is full.
+    ///
+    /// Fail if the queue is closed.
+    pub async fn send_async(&self, item: T) -> Result<(), SendError<T>> {
+        let reserved = futures::future::poll_fn(|cx| {
+            match self.queue.try_reserve_many(self.lane, 0, 1, true) {
+                Err(Rejected::Full) => {}
+                reserved => return Poll::Ready(reserved),
+            }
+
+            self.queue.register_sender(cx.waker());
+
+            // The receiver could have made some room before we registered.
+            match self.queue.try_reserve_many(self.lane, 0, 1, true) {
+                Err(Rejected::Full) => Poll::Pending,
+                reserved => Poll::Ready(reserved),
+            }
+        })
+        .await;
+
+        if let Err(rejected) = reserved {
+            return Err(rejected.with(item));
+        }
+
+        self.push(0, item);
+        Ok(())
+    }
+
+    /// Send the values of `items` to the queue, as many as the queue has room
+    /// for at once, the receiver is woken up once for each group of values.
+    ///
+    /// The sent values are removed from `items`, it is pending while the queue
+    /// is full and fails if the queue is closed.
+    pub(crate) fn poll_send_from(
+        &self,
+        cx: &mut Context<'_>,
+        items: &mut Vec<T>,
+    ) -> Poll<Result<(), Rejected>> {
+        while !items.is_empty() {
+            let reserved =
+                match self.queue.try_reserve_up_to(self.lane, 0, items.len()) {
+                    Ok(reserved) => reserved,
+                    Err(Rejected::Full) => {
+                        self.queue.register_sender(cx.waker());
+
+                        // The receiver could have made some room before we
+                        // registered.
+                        match self.queue.try_reserve_up_to(
+                            self.lane,
+                            0,
+                            items.len(),
+                        ) {
+                            Ok(reserved) => reserved,
+                            Err(Rejected::Full) => return Poll::Pending,
+                            Err(rejected) => return Poll::Ready(Err(rejected)),
+                        }
+                    }
+                    Err(rejected) => return Poll::Ready(Err(rejected)),
+                };
+
+            for item in items.drain(..reserved) {
+                self.queue.push_reserved(self.lane, 0, item);
+            }
+            self.queue.commit(0, reserved);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Close the queue this sender is sending to.
+    pub fn close(&self) {
+        self.queue.close();
+    }
+
+    /// End the receivers of the queue this sender is sending to, see
+    /// [`SharedQueueThreaded::retire_receivers`].
+    pub(crate) fn retire_receivers(&self) {
+        self.queue.retire_receivers();
+    }
+
+    /// Push an item for which a slot was already reserved.
+    fn push(&self, priority: usize, item: T) {
+        self.queue.push_reserved(self.lane, priority, item);
+        self.queue.commit(priority, 1);
     }
 }
 
+/// Receiving end of a queue, the values should be received by a single task.
 #[derive(Clone)]
 pub struct Receiver<T> {
     queue: Arc<SharedQueueThreaded<T>>,
+    /// Epoch of the queue when the receiver was created, it ends once the
+    /// shard it was taken from left the mesh.
+    epoch: usize,
+    mode: RecvMode,
+    /// When the last value was received, to tune the adaptive mode.
+    last_received: Option<Instant>,
+    /// Average time between two received values, to tune the adaptive mode.
+    average_gap: Duration,
+}
+
+/// How a [`Receiver`] waits for values when its queue is empty.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RecvMode {
+    /// Register the waker right away, the executor parks the task until a
+    /// value is sent.
+    #[default]
+    Park,
+    /// Spin on the queue for up to the given duration before registering the
+    /// waker, the thread is busy meanwhile.
+    Spin(Duration),
+    /// Spin for about the average time between two received values, up to
+    /// the given duration. Values sent further apart don't make the receiver
+    /// spin at all.
+    Adaptive(Duration),
+}
+
+/// Number of times the queue is checked between two checks of the clock while
+/// spinning.
+const SPINS_PER_CLOCK_CHECK: usize = 64;
+
+/// Error returned by [`Receiver::try_recv`] when no value could be received.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum TryRecvError {
+    #[error("The queue is empty.")]
+    Empty,
+    #[error("The queue is closed and every value was received.")]
+    Closed,
+}
+
+/// Error returned by [`Receiver::recv_timeout`] when no value could be
+/// received.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum RecvTimeoutError {
+    #[error("No value was received in time.")]
+    Timeout,
+    #[error("The queue is closed and every value was received.")]
+    Closed,
+}
+
+/// Unpark a thread blocked on a receiver when a value is sent.
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Set how the receiver waits for values when its queue is empty.
+    pub fn with_recv_mode(mut self, mode: RecvMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Attempts to receive a value without waiting.
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        match self.recv_up_to(1) {
+            0 if self.is_ended() => Err(TryRecvError::Closed),
+            0 => Err(TryRecvError::Empty),
+            _ => {
+                let item = self.queue.pop_counted();
+                self.queue.wake_senders();
+                self.received();
+                Ok(item)
+            }
+        }
+    }
+
+    /// Receive every value already in the queue without waiting, up to `max`
+    /// values, and append them to `buffer`.
+    ///
+    /// Return the number of values received.
+    pub fn recv_batch(&mut self, buffer: &mut Vec<T>, max: usize) -> usize {
+        let taken = self.recv_up_to(max);
+        if taken == 0 {
+            return 0;
+        }
+
+        buffer.reserve(taken);
+        for _ in 0..taken {
+            buffer.push(self.queue.pop_counted());
+        }
+
+        self.queue.wake_senders();
+        self.received();
+        taken
+    }
+
+    /// Wait for at least one value, then receive every value already in the
+    /// queue, up to `max` values, and append them to `buffer`.
+    ///
+    /// Return the number of values received, `0` only once the queue is
+    /// closed and every value was received or when `max` is `0`.
+    pub async fn recv_many(
+        &mut self,
+        buffer: &mut Vec<T>,
+        max: usize,
+    ) -> usize {
+        futures::future::poll_fn(|cx| {
+            if max == 0 {
+                return Poll::Ready(0);
+            }
+
+            self.spin();
+            self.queue.waker.register(cx.waker());
+
+            match self.recv_batch(buffer, max) {
+                0 if self.is_ended() => Poll::Ready(0),
+                0 => Poll::Pending,
+                taken => Poll::Ready(taken),
+            }
+        })
+        .await
+    }
+
+    /// Receive a value, parking the thread until one is sent.
+    ///
+    /// It is meant for peers running on a thread without an async runtime.
+    /// Return `None` once the queue is closed and every value was received.
+    pub fn recv_blocking(&mut self) -> Option<T> {
+        let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+
+        loop {
+            self.spin();
+            self.queue.waker.register(&waker);
+
+            match self.try_recv() {
+                Ok(item) => return Some(item),
+                Err(TryRecvError::Closed) => return None,
+                Err(TryRecvError::Empty) => std::thread::park(),
+            }
+        }
+    }
+
+    /// Receive a value, parking the thread until one is sent or until
+    /// `timeout` elapsed.
+    ///
+    /// It is meant for peers running on a thread without an async runtime. A
+    /// timeout too large to be represented waits like [`Self::recv_blocking`].
+    pub fn recv_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<T, RecvTimeoutError> {
+        let Some(deadline) = Instant::now().checked_add(timeout) else {
+            return self.recv_blocking().ok_or(RecvTimeoutError::Closed);
+        };
+        let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+
+        loop {
+            self.spin();
+            self.queue.waker.register(&waker);
+
+            match self.try_recv() {
+                Ok(item) => return Ok(item),
+                Err(TryRecvError::Closed) => {
+                    return Err(RecvTimeoutError::Closed)
+                }
+                Err(TryRecvError::Empty) => {}
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            std::thread::park_timeout(deadline - now);
+        }
+    }
+
+    /// Get the eventfd notified when values are sent to the queue, when the
+    /// mesh was built with [`MeshBuilder::with_eventfd`].
+    ///
+    /// [`MeshBuilder::with_eventfd`]: crate::mesh::MeshBuilder::with_eventfd
+    #[cfg(target_os = "linux")]
+    pub fn eventfd(&self) -> Option<&EventFd> {
+        self.queue.eventfd.as_deref()
+    }
+
+    /// Track the time between received values for the adaptive mode.
+    fn received(&mut self) {
+        if !matches!(self.mode, RecvMode::Adaptive(_)) {
+            return;
+        }
+
+        let now = Instant::now();
+        if let Some(last_received) = self.last_received.replace(now) {
+            let gap = now.saturating_duration_since(last_received);
+            self.average_gap = (self.average_gap * 7 + gap) / 8;
+        }
+    }
+
+    /// Spin until the queue has some items or is closed, for as long as the
+    /// receive mode allows.
+    fn spin(&self) {
+        let budget = match self.mode {
+            RecvMode::Park => return,
+            RecvMode::Spin(budget) => budget,
+            RecvMode::Adaptive(max) if self.average_gap <= max => {
+                self.average_gap
+            }
+            RecvMode::Adaptive(_) => return,
+        };
+
+        let start = Instant::now();
+        loop {
+            for _ in 0..SPINS_PER_CLOCK_CHECK {
+                if self
+                    .queue
+                    .task_queue
+                    .load(std::sync::atomic::Ordering::Acquire)
+                    != 0
+                {
+                    return;
+                }
+                std::hint::spin_loop();
+            }
+
+            if start.elapsed() >= budget {
+                return;
+            }
+        }
+    }
+
+    /// Check if the receiver won't receive any value anymore: the queue is
+    /// closed or the shard it was taken from left the mesh.
+    fn is_ended(&self) -> bool {
+        self.queue.is_closed() || self.is_retired()
+    }
+
+    /// Check if the shard the receiver was taken from left the mesh.
+    fn is_retired(&self) -> bool {
+        self.queue.epoch.load(std::sync::atomic::Ordering::Acquire)
+            != self.epoch
+    }
+
+    /// Take up to `max` items from the length of the queue in one update,
+    /// return how many items can be popped.
+    fn recv_up_to(&self, max: usize) -> usize {
+        if self.is_retired() {
+            return 0;
+        }
+
+        let mut taken = 0;
+        update_len(&self.queue.task_queue, |len| {
+            taken = (len & !CLOSED).min(max);
+            (taken > 0).then(|| len - taken)
+        });
+
+        taken
+    }
 }
 
 impl<T> Stream for Receiver<T> {
     type Item = T;
 
     fn poll_next(
-        self: std::pin::Pin<&mut Self>,
+        mut self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Option<Self::Item>> {
+        self.spin();
         self.queue.waker.register(cx.waker());
 
-        let old = self
-            .queue
-            .task_queue
-            .load(std::sync::atomic::Ordering::Relaxed);
-
-        if old > 0 {
-            self.queue
-                .task_queue
-                .fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
-            let item = self.queue.queue.pop_front_or_spin_wait_item();
-            Poll::Ready(Some(item))
-        } else {
-            Poll::Pending
+        match self.try_recv() {
+            Ok(item) => Poll::Ready(Some(item)),
+            // Every remaining item was received.
+            Err(TryRecvError::Closed) => Poll::Ready(None),
+            Err(TryRecvError::Empty) => Poll::Pending,
         }
     }
 }
@@ -104,9 +1090,12 @@ mod tests {
 
     use std::time::Duration;
 
-    use futures::StreamExt;
+    use futures::{Future, StreamExt};
 
-    use super::{SharedQueueChannels, SharedQueueThreaded};
+    use super::{
+        SharedQueueChannels, SharedQueueThreaded, ThreadWaker, TryRecvError,
+    };
+    use crate::shard::SendError;
 
     #[monoio::test_all(timer_enabled = true)]
     async fn ensure_send_receive() {
@@ -114,8 +1103,8 @@ mod tests {
 
         let (tx, mut rx) = queue.unbounded();
 
-        tx.send(1);
-        tx.send(2);
+        tx.send(1).unwrap();
+        tx.send(2).unwrap();
 
         let val1 = rx.next().await.unwrap();
         let val2 = rx.next().await.unwrap();
@@ -128,4 +1117,134 @@ mod tests {
         assert_eq!(merged, [1, 2]);
         assert!(val3.is_err());
     }
+
+    #[monoio::test_all(timer_enabled = true)]
+    async fn ensure_bounded_backpressure() {
+        let queue = SharedQueueThreaded::<u8>::with_capacity(2, Some(1));
+
+        let (tx, mut rx) = queue.unbounded();
+
+        assert!(tx.try_send(1).is_ok());
+        assert!(matches!(tx.try_send(2), Err(SendError::Full(2))));
+
+        let blocked =
+            monoio::time::timeout(Duration::from_millis(10), tx.send_async(2))
+                .await;
+        assert!(blocked.is_err());
+
+        let (sent, val1) = futures::join!(tx.send_async(2), rx.next());
+        assert!(sent.is_ok());
+        assert_eq!(val1, Some(1));
+        assert_eq!(rx.next().await, Some(2));
+    }
+
+    #[test]
+    fn ensure_waiting_senders_are_registered_once() {
+        let queue = SharedQueueThreaded::<u8>::with_capacity(2, Some(1));
+
+        let (tx, mut rx) = queue.unbounded();
+        assert!(tx.try_send(1).is_ok());
+
+        let waker = std::task::Waker::from(std::sync::Arc::new(ThreadWaker(
+            std::thread::current(),
+        )));
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut blocked = std::pin::pin!(tx.send_async(2));
+        assert!(blocked.as_mut().poll(&mut cx).is_pending());
+        assert!(blocked.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(queue.senders_waker.lock().unwrap().len(), 1);
+
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert!(queue.senders_waker.lock().unwrap().is_empty());
+        assert!(blocked.as_mut().poll(&mut cx).is_ready());
+    }
+
+    #[monoio::test_all(timer_enabled = true)]
+    async fn ensure_close_drains_then_ends() {
+        let queue = SharedQueueThreaded::<u8>::new(2).unwrap();
+
+        let (tx, mut rx) = queue.unbounded();
+
+        tx.send(1).unwrap();
+        queue.close();
+
+        assert!(matches!(tx.send(2), Err(SendError::Closed(2))));
+        assert_eq!(rx.next().await, Some(1));
+        assert_eq!(rx.next().await, None);
+    }
+
+    #[monoio::test_all(timer_enabled = true)]
+    async fn ensure_batches_without_waiting() {
+        let queue = SharedQueueThreaded::<u8>::with_capacity(2, Some(4));
+
+        let (tx, mut rx) = queue.unbounded();
+
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+
+        for val in 0..4 {
+            tx.try_send(val).unwrap();
+        }
+
+        let mut buffer = Vec::new();
+        assert_eq!(rx.recv_batch(&mut buffer, 3), 3);
+        assert_eq!(rx.recv_batch(&mut buffer, 3), 1);
+        assert_eq!(rx.recv_batch(&mut buffer, 3), 0);
+        buffer.sort();
+        assert_eq!(buffer, [0, 1, 2, 3]);
+
+        let (sent, received) = futures::join!(
+            async {
+                monoio::time::sleep(Duration::from_millis(10)).await;
+                tx.send(4)
+            },
+            rx.recv_many(&mut buffer, 8)
+        );
+        assert!(sent.is_ok());
+        assert_eq!(received, 1);
+        assert_eq!(buffer.last(), Some(&4));
+
+        tx.send(5).unwrap();
+        queue.close();
+        assert_eq!(rx.try_recv(), Ok(5));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Closed));
+        assert_eq!(rx.recv_many(&mut buffer, 8).await, 0);
+    }
+
+    #[monoio::test_all(timer_enabled = true)]
+    async fn ensure_batch_is_sent_entirely_or_not_at_all() {
+        let queue = SharedQueueThreaded::<u8>::with_capacity(2, Some(3));
+
+        let (tx, mut rx) = queue.unbounded();
+
+        tx.try_send(0).unwrap();
+        let rejected = tx.try_send_batch(vec![1, 2, 3]);
+        assert!(
+            matches!(rejected, Err(SendError::Full(ref batch)) if batch.len() == 3)
+        );
+
+        tx.try_send_batch(vec![1, 2]).unwrap();
+        let mut buffer = Vec::new();
+        assert_eq!(rx.recv_many(&mut buffer, 8).await, 3);
+        buffer.sort();
+        assert_eq!(buffer, [0, 1, 2]);
+    }
+
+    #[test]
+    fn ensure_a_single_level_is_not_counted() {
+        let queue = SharedQueueThreaded::<u8>::new(2).unwrap();
+
+        let (tx, mut rx) = queue.unbounded();
+        tx.try_send_batch(vec![0, 1, 2]).unwrap();
+        assert_eq!(
+            queue.levels[0]
+                .len
+                .load(std::sync::atomic::Ordering::Relaxed),
+            0
+        );
+
+        let mut buffer = Vec::new();
+        assert_eq!(rx.recv_batch(&mut buffer, 8), 3);
+        buffer.sort();
+        assert_eq!(buffer, [0, 1, 2]);
+    }
 }
@@ -0,0 +1,183 @@
+//! Run closures on other shards of the mesh and get their result back.
+//!
+//! Closures are sent to the targeted shard through the same kind of queue as
+//! the values sent between shards, the targeted shard runs them on its own
+//! executor as long as it drives its [`RemoteTasks`].
+
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::channel::oneshot;
+use futures::future::LocalBoxFuture;
+use futures::stream::FuturesUnordered;
+use futures::{FutureExt, Stream, StreamExt};
+
+use crate::queue::Receiver;
+use crate::shard::SendError;
+
+/// A closure sent to a shard, it creates the future to run on the shard.
+pub(crate) type Task = Box<dyn FnOnce() -> LocalBoxFuture<'static, ()> + Send>;
+
+/// Error returned when a task couldn't run on the targeted shard.
+#[derive(Debug, thiserror::Error)]
+pub enum RemoteError {
+    #[error("You can't spawn a task on a shard that doesn't exist.")]
+    WrongShard,
+    #[error("The shard did not join the mesh yet.")]
+    NotJoined,
+    #[error("The shard shut down before running the task.")]
+    Closed,
+    #[error("The task panicked on the shard.")]
+    Panicked,
+}
+
+impl<T> From<SendError<T>> for RemoteError {
+    fn from(value: SendError<T>) -> Self {
+        match value {
+            SendError::WrongShard(_) => RemoteError::WrongShard,
+            SendError::NotJoined(_) => RemoteError::NotJoined,
+            // The queue of the remote tasks is never bounded.
+            SendError::Full(_) | SendError::Closed(_) => RemoteError::Closed,
+        }
+    }
+}
+
+/// Create the task which runs `f` on a shard, and the receiving end of its
+/// result.
+pub(crate) fn task<F, Fut>(
+    f: F,
+) -> (Task, oneshot::Receiver<Result<Fut::Output, RemoteError>>)
+where
+    F: FnOnce() -> Fut + Send + 'static,
+    Fut: Future + 'static,
+    Fut::Output: Send + 'static,
+{
+    let (tx, rx) = oneshot::channel();
+
+    let task: Task = Box::new(move || {
+        async move {
+            let result = AssertUnwindSafe(async move { f().await })
+                .catch_unwind()
+                .await
+                .map_err(|_| RemoteError::Panicked);
+
+            // The handle may have been dropped, nobody is waiting for the
+            // result then.
+            let _ = tx.send(result);
+        }
+        .boxed_local()
+    });
+
+    (task, rx)
+}
+
+/// A handle to a task spawned on another shard, it resolves to the result of
+/// the task.
+///
+/// Dropping the handle detaches the task: it still runs on the shard but its
+/// result is discarded.
+#[must_use = "a JoinHandle does nothing unless polled"]
+pub struct JoinHandle<R> {
+    receiver: Option<oneshot::Receiver<Result<R, RemoteError>>>,
+    rejected: Option<RemoteError>,
+}
+
+impl<R> JoinHandle<R> {
+    pub(crate) fn new(
+        receiver: oneshot::Receiver<Result<R, RemoteError>>,
+    ) -> Self {
+        Self {
+            receiver: Some(receiver),
+            rejected: None,
+        }
+    }
+
+    pub(crate) fn rejected(error: RemoteError) -> Self {
+        Self {
+            receiver: None,
+            rejected: Some(error),
+        }
+    }
+}
+
+impl<R> std::fmt::Debug for JoinHandle<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "JoinHandle")
+    }
+}
+
+impl<R> Future for JoinHandle<R> {
+    type Output = Result<R, RemoteError>;
+
+    fn poll(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Self::Output> {
+        if let Some(error) = self.rejected.take() {
+            return Poll::Ready(Err(error));
+        }
+
+        let Some(receiver) = self.receiver.as_mut() else {
+            return Poll::Ready(Err(RemoteError::Closed));
+        };
+
+        // The task was dropped without running when the result is canceled.
+        receiver
+            .poll_unpin(cx)
+            .map(|result| result.unwrap_or(Err(RemoteError::Closed)))
+    }
+}
+
+/// Run the tasks sent to a shard by the other shards.
+///
+/// It must be spawned on the executor of the shard, it resolves once the shard
+/// is closed and every task it received is done.
+#[must_use = "RemoteTasks does nothing unless polled"]
+pub struct RemoteTasks {
+    receiver: Receiver<Task>,
+    running: FuturesUnordered<LocalBoxFuture<'static, ()>>,
+    closed: bool,
+}
+
+impl RemoteTasks {
+    pub(crate) fn new(receiver: Receiver<Task>) -> Self {
+        Self {
+            receiver,
+            running: FuturesUnordered::new(),
+            closed: false,
+        }
+    }
+}
+
+impl std::fmt::Debug for RemoteTasks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RemoteTasks")
+    }
+}
+
+impl Future for RemoteTasks {
+    type Output = ();
+
+    fn poll(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Self::Output> {
+        while !self.closed {
+            match Pin::new(&mut self.receiver).poll_next(cx) {
+                Poll::Ready(Some(task)) => self.running.push(task()),
+                Poll::Ready(None) => self.closed = true,
+                Poll::Pending => break,
+            }
+        }
+
+        while let Poll::Ready(Some(())) = self.running.poll_next_unpin(cx) {}
+
+        if self.closed && self.running.is_empty() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
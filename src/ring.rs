@@ -0,0 +1,137 @@
+//! Bounded lock-free ring with a single producer and a single consumer.
+
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Keep the producer and the consumer positions on their own cache line.
+#[repr(align(64))]
+struct CachePadded<T>(T);
+
+/// A bounded ring buffer where values are pushed by a single producer and
+/// popped by a single consumer, without any lock.
+pub(crate) struct SpscRing<T> {
+    buffer: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    /// Position of the next value to pop, only written by the consumer.
+    head: CachePadded<AtomicUsize>,
+    /// Position of the next value to push, only written by the producer.
+    tail: CachePadded<AtomicUsize>,
+}
+
+// SAFETY: values are moved between the producer and the consumer, each slot
+// is only accessed by one of them at a time.
+unsafe impl<T: Send> Send for SpscRing<T> {}
+unsafe impl<T: Send> Sync for SpscRing<T> {}
+
+impl<T> SpscRing<T> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            buffer: (0..capacity)
+                .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+                .collect(),
+            head: CachePadded(AtomicUsize::new(0)),
+            tail: CachePadded(AtomicUsize::new(0)),
+        }
+    }
+
+    fn slot(&self, position: usize) -> *mut MaybeUninit<T> {
+        self.buffer[position % self.buffer.len()].get()
+    }
+
+    /// Number of values which can still be pushed.
+    ///
+    /// It can only grow until the producer pushes again.
+    pub(crate) fn free(&self) -> usize {
+        let head = self.head.0.load(Ordering::Acquire);
+        let tail = self.tail.0.load(Ordering::Relaxed);
+        self.buffer.len() - tail.wrapping_sub(head)
+    }
+
+    /// Push a value, give it back if the ring is full.
+    ///
+    /// # Safety
+    ///
+    /// Only one thread can push at a time.
+    pub(crate) unsafe fn push(&self, val: T) -> Result<(), T> {
+        let tail = self.tail.0.load(Ordering::Relaxed);
+        let head = self.head.0.load(Ordering::Acquire);
+
+        if tail.wrapping_sub(head) == self.buffer.len() {
+            return Err(val);
+        }
+
+        // SAFETY: the slot is free, the consumer won't read it until the tail
+        // is published.
+        unsafe { (*self.slot(tail)).write(val) };
+        self.tail.0.store(tail.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    /// Pop a value, `None` if the ring is empty.
+    ///
+    /// # Safety
+    ///
+    /// Only one thread can pop at a time.
+    pub(crate) unsafe fn pop(&self) -> Option<T> {
+        let head = self.head.0.load(Ordering::Relaxed);
+        let tail = self.tail.0.load(Ordering::Acquire);
+
+        if head == tail {
+            return None;
+        }
+
+        // SAFETY: the slot was written by the producer before it published the
+        // tail, the producer won't write it again until the head is published.
+        let val = unsafe { (*self.slot(head)).assume_init_read() };
+        self.head.0.store(head.wrapping_add(1), Ordering::Release);
+        Some(val)
+    }
+}
+
+impl<T> Drop for SpscRing<T> {
+    fn drop(&mut self) {
+        // SAFETY: the ring is owned here, nobody else can push or pop.
+        while unsafe { self.pop() }.is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::SpscRing;
+
+    #[test]
+    fn ensure_fifo_across_threads() {
+        let ring = Arc::new(SpscRing::<usize>::new(8));
+
+        let producer = std::thread::spawn({
+            let ring = ring.clone();
+            move || {
+                for val in 0..10_000 {
+                    // SAFETY: this thread is the only producer.
+                    while unsafe { ring.push(val) }.is_err() {
+                        // Let the consumer run on a single core.
+                        std::thread::yield_now();
+                    }
+                }
+            }
+        });
+
+        let mut expected = 0;
+        while expected < 10_000 {
+            // SAFETY: this thread is the only consumer.
+            match unsafe { ring.pop() } {
+                Some(val) => {
+                    assert_eq!(val, expected);
+                    expected += 1;
+                }
+                None => std::thread::yield_now(),
+            }
+        }
+
+        producer.join().unwrap();
+        assert_eq!(ring.free(), 8);
+    }
+}
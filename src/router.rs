@@ -0,0 +1,168 @@
+//! Route values to shards depending on a key.
+//!
+//! The key is hashed with the hasher configured on the mesh, then a [`Router`]
+//! picks the shard owning this hash. Some routers keep most keys on the same
+//! shard when the number of shards changes.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{BuildHasher, BuildHasherDefault, Hash, Hasher};
+use std::sync::Arc;
+
+/// Pick the shard owning a hashed key.
+pub trait Router: Send + Sync + 'static {
+    /// Get the shard owning `hash` in a mesh of `nr_shards` shards, it must be
+    /// lower than `nr_shards`. A mesh has at least one shard.
+    fn route(&self, hash: u64, nr_shards: usize) -> usize;
+}
+
+/// Route a key to `hash % nr_shards`.
+///
+/// Almost every key moves to another shard when the number of shards changes.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Modulo;
+
+impl Router for Modulo {
+    fn route(&self, hash: u64, nr_shards: usize) -> usize {
+        (hash % nr_shards as u64) as usize
+    }
+}
+
+/// Route a key with the jump consistent hash algorithm from Lamping and Veach.
+///
+/// When a shard is added, only `1 / nr_shards` of the keys move to the new
+/// shard. Shards can only be added or removed at the end of the mesh.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct JumpConsistentHash;
+
+impl Router for JumpConsistentHash {
+    fn route(&self, hash: u64, nr_shards: usize) -> usize {
+        let mut key = hash;
+        let mut b: i64 = -1;
+        let mut j: i64 = 0;
+
+        while j < nr_shards as i64 {
+            b = j;
+            key = key.wrapping_mul(2862933555777941757).wrapping_add(1);
+            j = ((b + 1) as f64 * ((1u64 << 31) as f64)
+                / (((key >> 33) + 1) as f64)) as i64;
+        }
+
+        b as usize
+    }
+}
+
+/// Route a key with rendezvous hashing (highest random weight): every shard
+/// gets a score for the key and the highest score wins.
+///
+/// Only the keys of a removed shard move, wherever the shard is in the mesh,
+/// but the cost of routing grows with the number of shards.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Rendezvous;
+
+impl Router for Rendezvous {
+    fn route(&self, hash: u64, nr_shards: usize) -> usize {
+        (0..nr_shards)
+            .max_by_key(|&shard| mix(hash ^ mix(shard as u64)))
+            .unwrap_or(0)
+    }
+}
+
+/// Finalizer of splitmix64, spread the bits of `val`.
+fn mix(val: u64) -> u64 {
+    let mut z = val.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// Object safe version of [`BuildHasher`].
+trait KeyHasher: Send + Sync + 'static {
+    fn hash_with(&self, hash: &dyn Fn(&mut dyn Hasher)) -> u64;
+}
+
+impl<S> KeyHasher for S
+where
+    S: BuildHasher + Send + Sync + 'static,
+{
+    fn hash_with(&self, hash: &dyn Fn(&mut dyn Hasher)) -> u64 {
+        let mut hasher = self.build_hasher();
+        hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// How keys are routed to shards, shared by every shard of a mesh.
+#[derive(Clone)]
+pub(crate) struct Routing {
+    router: Arc<dyn Router>,
+    hasher: Arc<dyn KeyHasher>,
+}
+
+impl Default for Routing {
+    fn default() -> Self {
+        Self {
+            router: Arc::new(Modulo),
+            hasher: Arc::new(BuildHasherDefault::<DefaultHasher>::default()),
+        }
+    }
+}
+
+impl Routing {
+    pub(crate) fn with_router<R: Router>(self, router: R) -> Self {
+        Self {
+            router: Arc::new(router),
+            ..self
+        }
+    }
+
+    pub(crate) fn with_hasher<S>(self, hasher: S) -> Self
+    where
+        S: BuildHasher + Send + Sync + 'static,
+    {
+        Self {
+            hasher: Arc::new(hasher),
+            ..self
+        }
+    }
+
+    /// Get the shard owning `key` in a mesh of `nr_shards` shards.
+    pub(crate) fn shard_for_key<K: Hash + ?Sized>(
+        &self,
+        key: &K,
+        nr_shards: usize,
+    ) -> usize {
+        let hash = self.hasher.hash_with(&|mut state| key.hash(&mut state));
+        self.router.route(hash, nr_shards)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{JumpConsistentHash, Modulo, Rendezvous, Router};
+
+    fn moved_keys<R: Router>(router: R, from: usize, to: usize) -> usize {
+        (0..10_000u64)
+            .map(|key| key.wrapping_mul(0x9e3779b97f4a7c15))
+            .filter(|&hash| router.route(hash, from) != router.route(hash, to))
+            .count()
+    }
+
+    #[test]
+    fn ensure_routes_are_in_range() {
+        for nr_shards in 1..16 {
+            for hash in [0, 1, 42, u64::MAX] {
+                assert!(Modulo.route(hash, nr_shards) < nr_shards);
+                assert!(JumpConsistentHash.route(hash, nr_shards) < nr_shards);
+                assert!(Rendezvous.route(hash, nr_shards) < nr_shards);
+            }
+        }
+    }
+
+    #[test]
+    fn ensure_consistent_routers_move_few_keys() {
+        // Adding a fifth shard should move around a fifth of the keys.
+        assert!(moved_keys(JumpConsistentHash, 4, 5) < 2_500);
+        assert!(moved_keys(Rendezvous, 4, 5) < 2_500);
+        assert!(moved_keys(Modulo, 4, 5) > 5_000);
+    }
+}
@@ -0,0 +1,148 @@
+//! Request/response calls between shards.
+//!
+//! A mesh carrying [`Request`] allows a shard to
+//! [`call`](crate::shard::Shard::call) another shard and wait for its response:
+//! the request is routed through the mesh with a reply slot, the handler on the
+//! targeted shard answers it through the [`Responder`].
+
+use std::future::Future;
+
+use futures::channel::oneshot;
+use futures::future::Either;
+
+use crate::shard::{SendError, Shard, Target};
+
+/// A request sent to a shard, waiting for a response.
+pub struct Request<Req, Resp> {
+    request: Req,
+    responder: Responder<Resp>,
+}
+
+impl<Req, Resp> Request<Req, Resp> {
+    /// Get the request.
+    pub fn request(&self) -> &Req {
+        &self.request
+    }
+
+    /// Split the request to handle it and reply to the caller later.
+    pub fn into_parts(self) -> (Req, Responder<Resp>) {
+        (self.request, self.responder)
+    }
+}
+
+impl<Req, Resp> std::fmt::Debug for Request<Req, Resp> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Request")
+    }
+}
+
+/// Reply slot of a [`Request`], used by the handler to answer the caller.
+///
+/// Dropping it without replying makes the call fail with
+/// [`CallError::Dropped`].
+pub struct Responder<Resp> {
+    reply: oneshot::Sender<Resp>,
+}
+
+impl<Resp> Responder<Resp> {
+    /// Reply to the caller.
+    ///
+    /// Fail if the caller doesn't wait for the response anymore, the response
+    /// is given back.
+    pub fn reply(self, response: Resp) -> Result<(), Resp> {
+        self.reply.send(response)
+    }
+
+    /// Check if the caller stopped waiting for the response, because it timed
+    /// out or dropped the call.
+    pub fn is_canceled(&self) -> bool {
+        self.reply.is_canceled()
+    }
+}
+
+impl<Resp> std::fmt::Debug for Responder<Resp> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Responder")
+    }
+}
+
+/// Error returned when a call didn't get a response.
+#[derive(Debug, thiserror::Error)]
+pub enum CallError {
+    #[error("You can't call a shard that doesn't exist.")]
+    WrongShard,
+    #[error("The shard did not join the mesh yet.")]
+    NotJoined,
+    #[error("The queue of the shard is full.")]
+    Full,
+    #[error("The shard is closed.")]
+    Closed,
+    #[error("The shard dropped the request without replying.")]
+    Dropped,
+    #[error("The shard did not reply in time.")]
+    Timeout,
+}
+
+impl<T> From<SendError<T>> for CallError {
+    fn from(value: SendError<T>) -> Self {
+        match value {
+            SendError::WrongShard(_) => CallError::WrongShard,
+            SendError::NotJoined(_) => CallError::NotJoined,
+            SendError::Full(_) => CallError::Full,
+            SendError::Closed(_) => CallError::Closed,
+        }
+    }
+}
+
+/// Create a request and the receiving end of its response.
+fn request_with_reply<Req, Resp>(
+    request: Req,
+) -> (Request<Req, Resp>, oneshot::Receiver<Resp>) {
+    let (reply, response) = oneshot::channel();
+    let request = Request {
+        request,
+        responder: Responder { reply },
+    };
+
+    (request, response)
+}
+
+impl<Req, Resp> Shard<Request<Req, Resp>> {
+    /// Send a request to a shard and wait for its response.
+    ///
+    /// Waits for the shard to make some room if its queue is full. Dropping
+    /// the returned future releases the reply slot, the handler can notice it
+    /// with [`Responder::is_canceled`].
+    pub async fn call(
+        &self,
+        request: Req,
+        shard: impl Target,
+    ) -> Result<Resp, CallError> {
+        let (request, response) = request_with_reply(request);
+        self.send_to_async(request, shard).await?;
+        response.await.map_err(|_| CallError::Dropped)
+    }
+
+    /// Send a request to a shard and wait for its response until `delay`
+    /// resolves.
+    ///
+    /// The delay is a future so any runtime timer can be used, e.g.
+    /// `monoio::time::sleep(duration)`.
+    pub async fn call_timeout<D>(
+        &self,
+        request: Req,
+        shard: impl Target,
+        delay: D,
+    ) -> Result<Resp, CallError>
+    where
+        D: Future<Output = ()>,
+    {
+        let call = std::pin::pin!(self.call(request, shard));
+        let delay = std::pin::pin!(delay);
+
+        match futures::future::select(call, delay).await {
+            Either::Left((response, _)) => response,
+            Either::Right(((), _)) => Err(CallError::Timeout),
+        }
+    }
+}
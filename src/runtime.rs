@@ -0,0 +1,272 @@
+//! Launch a thread-per-core mesh on `monoio` runtimes.
+//!
+//! [`MeshRuntime`] spawns a thread for each CPU, pins it, builds its runtime,
+//! joins the mesh and runs an entry future with the [`Shard`] of the thread:
+//!
+//! ```rust
+//! use sharded_thread::runtime::MeshRuntime;
+//!
+//! let handle = MeshRuntime::<usize>::new(vec![0, 0])
+//!     .unwrap()
+//!     .launch(|shard| async move { shard.id().index() })
+//!     .unwrap();
+//!
+//! assert_eq!(handle.join().unwrap(), [0, 1]);
+//! ```
+
+use std::any::Any;
+use std::future::Future;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use monoio::time::TimeDriver;
+use monoio::{FusionDriver, RuntimeBuilder};
+
+use crate::mesh::{JoinError, MeshBuilder};
+use crate::shard::Shard;
+
+/// Builder of the runtime of each shard, the timer is always enabled.
+pub type Builder = RuntimeBuilder<TimeDriver<FusionDriver>>;
+
+/// Hook configuring the builder of the runtime of each shard.
+type Configure = dyn Fn(Builder) -> Builder + Send + Sync;
+
+/// Error returned when a shard of a [`MeshRuntime`] failed.
+#[derive(Debug, thiserror::Error)]
+pub enum RuntimeError {
+    #[error("The shard {shard} couldn't be pinned to its CPU: {error}")]
+    Pin { shard: usize, error: std::io::Error },
+    #[error("The runtime of the shard {shard} couldn't be built: {error}")]
+    Build { shard: usize, error: std::io::Error },
+    #[error("The shard {shard} couldn't join the mesh: {error}")]
+    Join { shard: usize, error: JoinError },
+    #[error("The shard {shard} panicked.")]
+    Panicked {
+        shard: usize,
+        payload: Box<dyn Any + Send>,
+    },
+}
+
+impl RuntimeError {
+    /// Id of the shard which failed.
+    pub fn shard(&self) -> usize {
+        match self {
+            RuntimeError::Pin { shard, .. }
+            | RuntimeError::Build { shard, .. }
+            | RuntimeError::Join { shard, .. }
+            | RuntimeError::Panicked { shard, .. } => *shard,
+        }
+    }
+
+    /// Resume the panic of the shard, other errors are returned as is.
+    pub fn resume_unwind(self) -> Self {
+        match self {
+            RuntimeError::Panicked { payload, .. } => {
+                std::panic::resume_unwind(payload)
+            }
+            error => error,
+        }
+    }
+}
+
+/// Launcher running each shard of a mesh on its own thread, pinned to its CPU,
+/// with its own `monoio` runtime.
+///
+/// The mesh can have a fixed number of peers `N`, see [`MeshBuilder::fixed`].
+pub struct MeshRuntime<T, const N: usize = 0> {
+    mesh: Arc<MeshBuilder<T, N>>,
+    /// CPU of each shard, the shard `i` runs on `cpus[i]`.
+    cpus: Vec<usize>,
+    configure: Arc<Configure>,
+}
+
+impl<T, const N: usize> std::fmt::Debug for MeshRuntime<T, N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MeshRuntime")
+            .field("cpus", &self.cpus)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: Send + 'static> MeshRuntime<T> {
+    /// Create a launcher with a mesh of a peer for each CPU of `cpus`.
+    pub fn new(cpus: Vec<usize>) -> std::io::Result<Self> {
+        let mesh = MeshBuilder::new(cpus.len())?;
+        Ok(Self::with_mesh(mesh, cpus))
+    }
+}
+
+impl<T: Send + 'static, const N: usize> MeshRuntime<T, N> {
+    /// Create a launcher running the peers of `mesh`, the shard `i` runs on
+    /// `cpus[i]`.
+    ///
+    /// The mesh can be configured beforehand, a shard is launched for each
+    /// CPU so the mesh needs at least as many peers as CPUs.
+    pub fn with_mesh(mesh: MeshBuilder<T, N>, cpus: Vec<usize>) -> Self {
+        Self {
+            mesh: Arc::new(mesh),
+            cpus,
+            configure: Arc::new(|builder| builder),
+        }
+    }
+
+    /// Configure the builder of the runtime of each shard, e.g. its number of
+    /// io_uring entries.
+    pub fn with_runtime<F>(mut self, configure: F) -> Self
+    where
+        F: Fn(Builder) -> Builder + Send + Sync + 'static,
+    {
+        self.configure = Arc::new(configure);
+        self
+    }
+
+    /// Get the mesh the shards are joining.
+    pub fn mesh(&self) -> &Arc<MeshBuilder<T, N>> {
+        &self.mesh
+    }
+
+    /// Spawn the thread of each shard, which runs `entry` with its [`Shard`]
+    /// until it resolves.
+    ///
+    /// Fail if a thread couldn't be spawned: the mesh is shut down then and
+    /// the threads already spawned are joined before returning.
+    pub fn launch<F, Fut>(
+        self,
+        entry: F,
+    ) -> std::io::Result<MeshRuntimeHandle<T, Fut::Output, N>>
+    where
+        F: Fn(Shard<T>) -> Fut + Send + Sync + 'static,
+        Fut: Future + 'static,
+        Fut::Output: Send + 'static,
+    {
+        let entry = Arc::new(entry);
+        let mut threads = Vec::with_capacity(self.cpus.len());
+
+        for (shard, &cpu) in self.cpus.iter().enumerate() {
+            let mesh = self.mesh.clone();
+            let configure = self.configure.clone();
+            let entry = entry.clone();
+
+            let spawned = std::thread::Builder::new()
+                .name(format!("shard-{shard}"))
+                .spawn(move || {
+                    run_shard(shard, cpu, &mesh, &*configure, &*entry)
+                });
+
+            match spawned {
+                Ok(thread) => threads.push(thread),
+                Err(error) => {
+                    // The shards already running would wait for the missing
+                    // ones forever.
+                    self.mesh.shutdown();
+                    for thread in threads {
+                        let _ = thread.join();
+                    }
+                    return Err(error);
+                }
+            }
+        }
+
+        Ok(MeshRuntimeHandle {
+            mesh: self.mesh,
+            threads,
+        })
+    }
+}
+
+/// Pin the thread, build its runtime and run `entry` with the shard.
+///
+/// The mesh is shut down if the shard fails, even if it panics, so the other
+/// shards don't wait for it forever.
+fn run_shard<T, F, Fut, const N: usize>(
+    shard: usize,
+    cpu: usize,
+    mesh: &MeshBuilder<T, N>,
+    configure: &Configure,
+    entry: &F,
+) -> Result<Fut::Output, RuntimeError>
+where
+    F: Fn(Shard<T>) -> Fut,
+    Fut: Future,
+{
+    let mut failure = ShutdownOnFailure { mesh, failed: true };
+
+    monoio::utils::bind_to_cpu_set(Some(cpu)).map_err(|error| {
+        RuntimeError::Pin {
+            shard,
+            error: error.into(),
+        }
+    })?;
+
+    let mut runtime = configure(RuntimeBuilder::new().enable_timer())
+        .build()
+        .map_err(|error| RuntimeError::Build { shard, error })?;
+    let joined = mesh
+        .peer_id(shard)
+        .ok_or_else(|| JoinError::OutOfRange {
+            peer: shard,
+            nr_peers: mesh.peer_ids().count(),
+        })
+        .and_then(|id| mesh.join(id))
+        .map_err(|error| RuntimeError::Join { shard, error })?;
+
+    let output = runtime.block_on(entry(joined));
+    failure.failed = false;
+    Ok(output)
+}
+
+/// Shut the mesh down when dropped while the shard is still failed.
+struct ShutdownOnFailure<'a, T, const N: usize> {
+    mesh: &'a MeshBuilder<T, N>,
+    failed: bool,
+}
+
+impl<T, const N: usize> Drop for ShutdownOnFailure<'_, T, N> {
+    fn drop(&mut self) {
+        if self.failed {
+            self.mesh.shutdown();
+        }
+    }
+}
+
+/// Handle on the threads of a [`MeshRuntime`].
+pub struct MeshRuntimeHandle<T, R, const N: usize = 0> {
+    mesh: Arc<MeshBuilder<T, N>>,
+    threads: Vec<JoinHandle<Result<R, RuntimeError>>>,
+}
+
+impl<T, R, const N: usize> std::fmt::Debug for MeshRuntimeHandle<T, R, N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MeshRuntimeHandle")
+            .field("threads", &self.threads.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, R, const N: usize> MeshRuntimeHandle<T, R, N> {
+    /// Get the mesh the shards joined, e.g. to shut it down.
+    pub fn mesh(&self) -> &Arc<MeshBuilder<T, N>> {
+        &self.mesh
+    }
+
+    /// Wait for every thread to finish, return the output of the entry of
+    /// each shard.
+    ///
+    /// Every thread is waited for even if some of them failed, the error of
+    /// the first shard which failed is returned then. A panic of a shard is
+    /// reported with its payload, see [`RuntimeError::resume_unwind`].
+    pub fn join(self) -> Result<Vec<R>, RuntimeError> {
+        let results = self
+            .threads
+            .into_iter()
+            .enumerate()
+            .map(|(shard, thread)| {
+                thread.join().unwrap_or_else(|payload| {
+                    Err(RuntimeError::Panicked { shard, payload })
+                })
+            })
+            .collect::<Vec<_>>();
+
+        results.into_iter().collect()
+    }
+}
@@ -1,34 +1,287 @@
 use std::cell::Cell;
 use std::fmt::Debug;
-use std::sync::atomic::AtomicUsize;
+use std::future::Future;
+use std::hash::Hash;
+use std::marker::PhantomData;
 use std::sync::Arc;
 
+use crate::buffer::SendBuffer;
+use crate::channel::{Channel, ShardChannels};
+use crate::membership::Membership;
+use crate::mesh::{MeshClosed, NotReady};
 use crate::queue::{Receiver, Sender};
+use crate::remote::{JoinHandle, RemoteTasks, Task};
+use crate::router::Routing;
+use crate::sink::ShardSink;
 
-#[derive(Debug, thiserror::Error)]
-pub enum SenderError {
+/// Id of a shard, which can only be obtained from the mesh it belongs to.
+///
+/// Unlike a bare index, it can't be mixed up with a value nor used with
+/// another mesh: it is refused by the other meshes and their shards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ShardId {
+    index: usize,
+    pub(crate) mesh: u64,
+}
+
+impl ShardId {
+    pub(crate) fn new(index: usize, mesh: u64) -> Self {
+        Self { index, mesh }
+    }
+
+    /// Index of the shard in its mesh, between `0` and the number of peers.
+    pub fn index(self) -> usize {
+        self.index
+    }
+}
+
+impl std::fmt::Display for ShardId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.index)
+    }
+}
+
+/// Error returned when a value couldn't be sent to a shard.
+///
+/// The value is always given back so it can be sent somewhere else or
+/// released properly.
+#[derive(thiserror::Error)]
+pub enum SendError<T> {
     #[error("You can't send the value to a shard that doesn't exist.")]
+    WrongShard(T),
+    #[error("The shard did not join the mesh yet.")]
+    NotJoined(T),
+    #[error("The queue of the shard is full.")]
+    Full(T),
+    #[error("The shard is closed.")]
+    Closed(T),
+}
+
+impl<T> Debug for SendError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let variant = match self {
+            SendError::WrongShard(_) => "WrongShard",
+            SendError::NotJoined(_) => "NotJoined",
+            SendError::Full(_) => "Full",
+            SendError::Closed(_) => "Closed",
+        };
+        f.debug_tuple(variant).finish_non_exhaustive()
+    }
+}
+
+impl<T> SendError<T> {
+    /// Get back the value which couldn't be sent.
+    pub fn into_inner(self) -> T {
+        match self {
+            SendError::WrongShard(val)
+            | SendError::NotJoined(val)
+            | SendError::Full(val)
+            | SendError::Closed(val) => val,
+        }
+    }
+
+    /// Get a reference to the value which couldn't be sent.
+    pub fn inner(&self) -> &T {
+        match self {
+            SendError::WrongShard(val)
+            | SendError::NotJoined(val)
+            | SendError::Full(val)
+            | SendError::Closed(val) => val,
+        }
+    }
+
+    /// Check if the value could be sent later to the same shard, which is the
+    /// case when the shard is full or did not join yet.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, SendError::NotJoined(_) | SendError::Full(_))
+    }
+}
+
+/// Error returned when a value couldn't be sent to some of the shards of a
+/// broadcast, with the error of each of these shards.
+#[derive(thiserror::Error)]
+#[error("The value couldn't be sent to {} shard(s).", .failures.len())]
+pub struct BroadcastError<T> {
+    pub(crate) failures: Vec<(usize, SendError<T>)>,
+}
+
+impl<T> Debug for BroadcastError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BroadcastError")
+            .field("failures", &self.failures)
+            .finish()
+    }
+}
+
+impl<T> BroadcastError<T> {
+    /// Get the shards the value couldn't be sent to, with their error.
+    pub fn failures(&self) -> &[(usize, SendError<T>)] {
+        &self.failures
+    }
+
+    /// Get the shards the value couldn't be sent to, with their error which
+    /// gives the value back.
+    pub fn into_failures(self) -> Vec<(usize, SendError<T>)> {
+        self.failures
+    }
+}
+
+/// Reason why a shard can't be reached.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Unreachable {
     WrongShard,
+    NotJoined,
+}
+
+impl Unreachable {
+    pub(crate) fn with<T>(self, val: T) -> SendError<T> {
+        match self {
+            Unreachable::WrongShard => SendError::WrongShard(val),
+            Unreachable::NotJoined => SendError::NotJoined(val),
+        }
+    }
+}
+
+/// A shard values are sent to, by its index in the mesh or by its
+/// [`ShardId`].
+///
+/// An index is only checked against the number of peers of the mesh while an
+/// id is refused by the other meshes with [`SendError::WrongShard`]. It is
+/// sealed, only `usize` and [`ShardId`] are targets.
+pub trait Target: Copy + sealed::Sealed {
+    /// Index of the shard, reported when the value can't be sent.
+    fn index(self) -> usize;
+}
+
+impl Target for usize {
+    fn index(self) -> usize {
+        self
+    }
+}
+
+impl Target for ShardId {
+    fn index(self) -> usize {
+        ShardId::index(self)
+    }
+}
+
+pub(crate) mod sealed {
+    use super::ShardId;
+
+    pub trait Sealed {
+        /// Tag of the mesh the target belongs to, `None` for an index.
+        fn mesh(self) -> Option<u64>;
+    }
+
+    impl Sealed for usize {
+        fn mesh(self) -> Option<u64> {
+            None
+        }
+    }
+
+    impl Sealed for ShardId {
+        fn mesh(self) -> Option<u64> {
+            Some(self.mesh)
+        }
+    }
 }
 
 /// The structure which is used to communicate with other peers from the Mesh.
 pub struct Shard<T> {
     pub(crate) receiver: Cell<Option<Receiver<T>>>,
     pub(crate) senders: Vec<Sender<T>>,
-    /// Number of shard available
-    pub(crate) max_shard: Arc<AtomicUsize>,
+    /// Ends of the additional channels of the mesh.
+    pub(crate) channels: ShardChannels,
+    /// Receiver of the tasks spawned on this shard, the tasks are only run
+    /// once it is taken so the shard stays `Send`.
+    pub(crate) remote_tasks: Cell<Option<Receiver<Task>>>,
+    pub(crate) task_senders: Vec<Sender<Task>>,
+    pub(crate) routing: Routing,
+    /// Peers which are currently part of the mesh
+    pub(crate) membership: Arc<Membership>,
     /// Actual shard id
-    #[allow(dead_code)]
     pub(crate) shard_id: usize,
+    /// The senders to the rings must stay on one thread, see
+    /// [`Transport::Rings`].
+    ///
+    /// [`Transport::Rings`]: crate::mesh::Transport::Rings
+    pub(crate) not_sync: PhantomData<Cell<()>>,
 }
 
 impl<T> Debug for Shard<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Shard")
+        f.debug_struct("Shard")
+            .field("id", &self.shard_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Drop for Shard<T> {
+    fn drop(&mut self) {
+        // The receivers end before another shard can join with the same id.
+        self.senders[self.shard_id].retire_receivers();
+        self.task_senders[self.shard_id].retire_receivers();
+        self.channels.retire_receivers(self.shard_id);
+        self.membership.leave(self.shard_id);
     }
 }
 
 impl<T> Shard<T> {
+    /// Leave the mesh, which also happens when the shard is dropped.
+    ///
+    /// The other shards can't send values to this shard anymore and the
+    /// receivers taken from it end, values which were already sent stay in its
+    /// queue until a new shard joins the mesh with the same id.
+    pub fn leave(self) {
+        drop(self);
+    }
+
+    /// Get the id of this shard.
+    pub fn id(&self) -> ShardId {
+        self.membership
+            .peer_id(self.shard_id)
+            .expect("the shard is part of its mesh")
+    }
+
+    /// Number of peers of the mesh, whether they joined or not.
+    pub fn peers(&self) -> usize {
+        self.senders.len()
+    }
+
+    /// Number of peers which are currently part of the mesh, including this
+    /// shard.
+    pub fn joined_peers(&self) -> usize {
+        self.membership.len()
+    }
+
+    /// Get the id of every other peer of the mesh, whether they joined or not.
+    pub fn other_peers(&self) -> impl Iterator<Item = ShardId> + '_ {
+        (0..self.peers())
+            .filter(move |&peer| peer != self.shard_id)
+            .filter_map(|peer| self.membership.peer_id(peer))
+    }
+
+    /// Wait until every peer joined the mesh, see [`MeshBuilder::ready`].
+    ///
+    /// [`MeshBuilder::ready`]: crate::mesh::MeshBuilder::ready
+    pub async fn wait_all_joined(&self) -> Result<(), MeshClosed> {
+        self.membership.ready().await
+    }
+
+    /// Wait until every peer joined the mesh or until `delay` resolves, see
+    /// [`MeshBuilder::ready_timeout`].
+    ///
+    /// [`MeshBuilder::ready_timeout`]: crate::mesh::MeshBuilder::ready_timeout
+    pub async fn wait_all_joined_timeout<D>(
+        &self,
+        delay: D,
+    ) -> Result<(), NotReady>
+    where
+        D: Future<Output = ()>,
+    {
+        self.membership.ready_timeout(delay).await
+    }
+
     /// Take the receiver of this shard.
     /// Shard are implemented using `mpsc` channels, so only one Receiver can
     /// receiving values from the other shards.
@@ -36,33 +289,334 @@ impl<T> Shard<T> {
         self.receiver.take()
     }
 
-    /// Send a value to the proper shard
+    /// Get the channel carrying values of type `U`, `None` if the mesh has no
+    /// such channel, see
+    /// [`MeshBuilder::with_channel`](crate::mesh::MeshBuilder::with_channel).
+    pub fn channel<U: 'static>(&self) -> Option<Channel<'_, U>> {
+        Channel::new(self)
+    }
+
+    /// Take the tasks spawned on this shard by the other shards.
     ///
-    /// Fail if this Shard did not join yet.
-    pub fn send_to(&self, val: T, shard: usize) -> Result<(), SenderError> {
-        let max_shard =
-            self.max_shard.load(std::sync::atomic::Ordering::Acquire);
+    /// The returned future must be spawned on the executor of this shard for
+    /// the tasks to run, it resolves once this shard is closed.
+    pub fn remote_tasks(&self) -> Option<RemoteTasks> {
+        self.remote_tasks.take().map(RemoteTasks::new)
+    }
+
+    /// Get the index of `shard`, fail if it is the id of a shard of another
+    /// mesh.
+    fn index_of(&self, shard: impl Target) -> Result<usize, Unreachable> {
+        self.membership
+            .peer_of(shard)
+            .ok_or(Unreachable::WrongShard)
+    }
+
+    /// Get the sender of a shard which exists.
+    fn sender(&self, shard: impl Target) -> Result<&Sender<T>, Unreachable> {
+        let shard = self.index_of(shard)?;
+        self.senders.get(shard).ok_or(Unreachable::WrongShard)
+    }
+
+    /// Get the sender of a shard which joined the mesh.
+    pub(crate) fn joined_sender(
+        &self,
+        shard: impl Target,
+    ) -> Result<&Sender<T>, Unreachable> {
+        let sender = self.sender(shard)?;
 
-        if shard >= max_shard {
-            return Err(SenderError::WrongShard);
+        if !self.membership.is_joined(shard.index()) {
+            return Err(Unreachable::NotJoined);
         }
 
-        let sender = self
-            .senders
-            .get(shard)
-            .expect("the sender should have been here but he is not.");
+        Ok(sender)
+    }
+
+    /// Send a value to a shard, by its index or by its [`ShardId`].
+    ///
+    /// Fail if this Shard did not join yet, if the shard is closed or if the
+    /// queue of the shard is full. Fail with [`SendError::WrongShard`] if the
+    /// shard doesn't exist or if its id belongs to another mesh.
+    ///
+    /// Values are received in the order they were sent only when the mesh
+    /// guarantees it, see
+    /// [`MeshBuilder::with_fifo_per_sender`](crate::mesh::MeshBuilder::with_fifo_per_sender).
+    pub fn send_to(
+        &self,
+        val: T,
+        shard: impl Target,
+    ) -> Result<(), SendError<T>> {
+        self.try_send_to(val, shard)
+    }
 
-        sender.send(val);
-        Ok(())
+    /// Get the id of the shard at `index` in the mesh, `None` if it doesn't
+    /// exist.
+    pub fn peer_id(&self, index: usize) -> Option<ShardId> {
+        self.membership.peer_id(index)
+    }
+
+    /// Try to send a value to a shard, the value is given back if it can't be
+    /// sent.
+    ///
+    /// Fail like [`Shard::send_to`].
+    pub fn try_send_to(
+        &self,
+        val: T,
+        shard: impl Target,
+    ) -> Result<(), SendError<T>> {
+        match self.joined_sender(shard) {
+            Ok(sender) => sender.try_send(val),
+            Err(unreachable) => Err(unreachable.with(val)),
+        }
+    }
+
+    /// Send a value to a shard with a priority, the shard receives it before
+    /// the values sent with a lower priority.
+    ///
+    /// `0` is the lowest priority and the one used by the other methods, see
+    /// [`MeshBuilder::with_priorities`](crate::mesh::MeshBuilder::with_priorities).
+    /// Fail like [`Shard::send_to`].
+    pub fn send_to_with_priority(
+        &self,
+        val: T,
+        shard: impl Target,
+        priority: usize,
+    ) -> Result<(), SendError<T>> {
+        match self.joined_sender(shard) {
+            Ok(sender) => sender.try_send_with_priority(val, priority),
+            Err(unreachable) => Err(unreachable.with(val)),
+        }
+    }
+
+    /// Send a value to a shard, waiting for the shard to make some room if
+    /// its queue is full.
+    ///
+    /// Fail like [`Shard::send_to`], except when the queue is full.
+    pub async fn send_to_async(
+        &self,
+        val: T,
+        shard: impl Target,
+    ) -> Result<(), SendError<T>> {
+        match self.joined_sender(shard) {
+            Ok(sender) => sender.send_async(val).await,
+            Err(unreachable) => Err(unreachable.with(val)),
+        }
+    }
+
+    /// Send every value of `vals` to a shard at once, the shard is woken up
+    /// once for the whole batch.
+    ///
+    /// Either every value is sent or none: the values are given back if the
+    /// batch fails like [`Shard::send_to`], or if the queue of the shard
+    /// doesn't have room for all of them.
+    pub fn send_batch_to(
+        &self,
+        vals: impl IntoIterator<Item = T>,
+        shard: impl Target,
+    ) -> Result<(), SendError<Vec<T>>> {
+        let vals = vals.into_iter().collect();
+        match self.joined_sender(shard) {
+            Ok(sender) => sender.try_send_batch(vals),
+            Err(unreachable) => Err(unreachable.with(vals)),
+        }
+    }
+
+    /// Create a buffer which groups the values sent to each shard into
+    /// batches of `threshold` values, see [`SendBuffer`].
+    pub fn send_buffer(&self, threshold: usize) -> SendBuffer<'_, T> {
+        SendBuffer::new(self, threshold)
+    }
+
+    /// Create a [`Sink`](futures::Sink) sending values to a shard, see
+    /// [`ShardSink`].
+    ///
+    /// The values fail to be sent like with [`Shard::send_to`].
+    pub fn sink_to(&self, shard: impl Target) -> ShardSink<'_, T> {
+        let destination = self.index_of(shard);
+        ShardSink::new(self, destination)
+    }
+
+    /// Get the shard owning `key` with the router of the mesh, without
+    /// sending anything.
+    pub fn shard_for_key<K: Hash + ?Sized>(&self, key: &K) -> usize {
+        self.routing.shard_for_key(key, self.senders.len())
+    }
+
+    /// Send a value to the shard owning `key`.
+    ///
+    /// Fail like [`Shard::send_to`].
+    pub fn send_by_key<K: Hash + ?Sized>(
+        &self,
+        val: T,
+        key: &K,
+    ) -> Result<(), SendError<T>> {
+        self.send_to(val, self.shard_for_key(key))
+    }
+
+    /// Send a clone of a value to every shard of the mesh, including this one.
+    ///
+    /// The value is sent to every shard even if some of them fail, the shards
+    /// which failed are reported.
+    pub fn broadcast(&self, val: T) -> Result<(), BroadcastError<T>>
+    where
+        T: Clone,
+    {
+        self.send_to_each(val, 0..self.senders.len())
+    }
+
+    /// Send a clone of a value to every other shard of the mesh.
+    ///
+    /// The value is sent to every shard even if some of them fail, the shards
+    /// which failed are reported.
+    pub fn broadcast_to_others(&self, val: T) -> Result<(), BroadcastError<T>>
+    where
+        T: Clone,
+    {
+        let shard_id = self.shard_id;
+        self.send_to_each(
+            val,
+            (0..self.senders.len()).filter(move |&shard| shard != shard_id),
+        )
+    }
+
+    /// Send a clone of a value to each of the given shards, by their index or
+    /// by their [`ShardId`].
+    ///
+    /// The value is sent to every shard even if some of them fail, the shards
+    /// which failed are reported.
+    pub fn multicast(
+        &self,
+        val: T,
+        shards: &[impl Target],
+    ) -> Result<(), BroadcastError<T>>
+    where
+        T: Clone,
+    {
+        self.send_to_each(val, shards.iter().copied())
+    }
+
+    /// Send a clone of `val` to each shard, the last one gets `val` itself.
+    fn send_to_each(
+        &self,
+        val: T,
+        shards: impl Iterator<Item = impl Target>,
+    ) -> Result<(), BroadcastError<T>>
+    where
+        T: Clone,
+    {
+        let mut failures = Vec::new();
+        let mut shards = shards.peekable();
+        let mut val = Some(val);
+
+        while let Some(shard) = shards.next() {
+            let val = match shards.peek() {
+                Some(_) => val.clone(),
+                None => val.take(),
+            }
+            .expect("the value is only taken for the last shard");
+
+            if let Err(error) = self.send_to(val, shard) {
+                failures.push((shard.index(), error));
+            }
+        }
+
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(BroadcastError { failures })
+        }
     }
 
     /// Send a value to a shard
-    pub fn send_to_unchecked(&self, val: T, shard: usize) {
-        let sender = self
-            .senders
-            .get(shard)
-            .expect("the sender should have been here but he is not.");
+    ///
+    /// Neither the capacity of the shard nor the fact it joined are checked,
+    /// fail if the shard doesn't exist or is closed. With
+    /// [`Transport::Rings`] each ring can't hold more than its capacity, fail
+    /// with [`SendError::Full`] when the ring to the shard is full.
+    ///
+    /// [`Transport::Rings`]: crate::mesh::Transport::Rings
+    pub fn send_to_unchecked(
+        &self,
+        val: T,
+        shard: impl Target,
+    ) -> Result<(), SendError<T>> {
+        match self.sender(shard) {
+            Ok(sender) => sender.send(val),
+            Err(unreachable) => Err(unreachable.with(val)),
+        }
+    }
+
+    /// Close this shard: other shards can't send values to it anymore and its
+    /// receiver ends once every value already sent is received.
+    ///
+    /// A [`Shard`] joining later with the same id opens it again.
+    pub fn close(&self) {
+        self.senders[self.shard_id].close();
+        self.channels.close(self.shard_id);
+        self.task_senders[self.shard_id].close();
+    }
 
-        sender.send(val);
+    /// Spawn a task on another shard and get its result back.
+    ///
+    /// The closure is sent to the shard which creates the future and runs it
+    /// on its own executor, so the future itself doesn't have to be `Send`.
+    ///
+    /// Fail if the shard can't be reached like with [`Shard::send_to`], if it
+    /// shut down before running the task or if the task panicked.
+    pub fn spawn_on<F, Fut>(
+        &self,
+        f: F,
+        shard: impl Target,
+    ) -> JoinHandle<Fut::Output>
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future + 'static,
+        Fut::Output: Send + 'static,
+    {
+        let sender = match self.task_sender(shard) {
+            Ok(sender) => sender,
+            Err(unreachable) => {
+                return JoinHandle::rejected(unreachable.with(()).into())
+            }
+        };
+
+        let (task, result) = crate::remote::task(f);
+        match sender.send(task) {
+            Ok(()) => JoinHandle::new(result),
+            Err(error) => JoinHandle::rejected(error.into()),
+        }
+    }
+
+    /// Run a closure on another shard and get its result back.
+    ///
+    /// Fail like [`Shard::spawn_on`].
+    pub fn submit_to<F, R>(&self, f: F, shard: impl Target) -> JoinHandle<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        self.spawn_on(move || async move { f() }, shard)
+    }
+
+    /// Get the task sender of a shard which joined the mesh.
+    fn task_sender(
+        &self,
+        shard: impl Target,
+    ) -> Result<&Sender<Task>, Unreachable> {
+        self.joined_sender(shard)?;
+        Ok(&self.task_senders[shard.index()])
+    }
+}
+
+impl<U> Shard<Arc<U>> {
+    /// Share a value with every shard of the mesh, including this one.
+    ///
+    /// The value is allocated once and every shard gets a reference to it,
+    /// which avoids cloning large values for each shard.
+    pub fn broadcast_shared(
+        &self,
+        val: U,
+    ) -> Result<(), BroadcastError<Arc<U>>> {
+        self.broadcast(Arc::new(val))
     }
 }
@@ -0,0 +1,112 @@
+//! Send values to a shard through a [`Sink`].
+//!
+//! It allows to forward a stream straight into a shard, e.g. the receiver of
+//! another shard with [`StreamExt::forward`](futures::StreamExt::forward).
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::Sink;
+
+use crate::shard::{SendError, Shard, Unreachable};
+
+/// Number of values buffered by a [`ShardSink`] by default before they are
+/// sent.
+const DEFAULT_BUFFER_SIZE: usize = 64;
+
+/// A [`Sink`] sending the values of a [`Shard`] to another shard.
+///
+/// The values are buffered and sent as a batch when the sink is flushed or
+/// when the buffer is full, the receiving shard is woken up once per batch. The
+/// sink waits for the shard to make some room if its queue is full.
+///
+/// Closing the sink only flushes it, the shard it sends to is not closed.
+pub struct ShardSink<'a, T> {
+    shard: &'a Shard<T>,
+    /// Index of the shard the values are sent to, or why it can't be reached.
+    destination: Result<usize, Unreachable>,
+    buffer: Vec<T>,
+    buffer_size: usize,
+}
+
+impl<T> std::fmt::Debug for ShardSink<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ShardSink")
+    }
+}
+
+impl<'a, T> ShardSink<'a, T> {
+    pub(crate) fn new(
+        shard: &'a Shard<T>,
+        destination: Result<usize, Unreachable>,
+    ) -> Self {
+        Self {
+            shard,
+            destination,
+            buffer: Vec::new(),
+            buffer_size: DEFAULT_BUFFER_SIZE,
+        }
+    }
+
+    /// Set the number of values buffered before they are sent.
+    pub fn with_buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size.max(1);
+        self
+    }
+}
+
+// The sink doesn't hold any pinned data.
+impl<T> Unpin for ShardSink<'_, T> {}
+
+impl<T> Sink<T> for ShardSink<'_, T> {
+    /// The values which couldn't be sent are given back.
+    type Error = SendError<Vec<T>>;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        if self.buffer.len() < self.buffer_size {
+            return Poll::Ready(Ok(()));
+        }
+
+        self.poll_flush(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        self.get_mut().buffer.push(item);
+        Ok(())
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        if this.buffer.is_empty() {
+            return Poll::Ready(Ok(()));
+        }
+
+        let sender = match this
+            .destination
+            .and_then(|destination| this.shard.joined_sender(destination))
+        {
+            Ok(sender) => sender,
+            Err(unreachable) => {
+                let buffer = std::mem::take(&mut this.buffer);
+                return Poll::Ready(Err(unreachable.with(buffer)));
+            }
+        };
+
+        sender
+            .poll_send_from(cx, &mut this.buffer)
+            .map_err(|rejected| rejected.with(std::mem::take(&mut this.buffer)))
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.poll_flush(cx)
+    }
+}
@@ -0,0 +1,164 @@
+use std::time::Duration;
+
+use sharded_thread::barrier::BarrierError;
+use sharded_thread::mesh::MeshBuilder;
+use sharded_thread::shard::Shard;
+
+#[monoio::test_all(timer_enabled = true)]
+async fn barrier_is_reusable() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(3, 2).unwrap();
+    let barrier = mesh.barrier();
+
+    let shards: Vec<Shard<Msg>> =
+        (0..3).map(|peer| mesh.join_with(peer).unwrap()).collect();
+
+    for generation in 0..2 {
+        let (a, b, c) = futures::join!(
+            barrier.wait(&shards[0]),
+            barrier.wait(&shards[1]),
+            barrier.wait(&shards[2])
+        );
+        let results = [a.unwrap(), b.unwrap(), c.unwrap()];
+
+        assert!(results.iter().all(|r| r.generation() == generation));
+        assert_eq!(results.iter().filter(|r| r.is_leader()).count(), 1);
+    }
+
+    let early = monoio::time::timeout(
+        Duration::from_millis(10),
+        barrier.wait(&shards[0]),
+    )
+    .await;
+    assert!(early.is_err());
+
+    // The dropped wait withdrew from the barrier.
+    let (a, b, c) = futures::join!(
+        barrier.wait(&shards[0]),
+        barrier.wait(&shards[1]),
+        barrier.wait(&shards[2])
+    );
+    assert!(a.is_ok() && b.is_ok() && c.is_ok());
+}
+
+#[monoio::test_all(timer_enabled = true)]
+async fn barrier_fails_when_a_peer_leaves() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(3, 2).unwrap();
+    let barrier = mesh.barrier();
+
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+    let shard_2: Shard<Msg> = mesh.join_with(2).unwrap();
+
+    let leave = async {
+        monoio::time::sleep(Duration::from_millis(10)).await;
+        shard_2.leave();
+    };
+    let (a, b, ()) =
+        futures::join!(barrier.wait(&shard_0), barrier.wait(&shard_1), leave);
+    assert!(matches!(a, Err(BarrierError::PeerLeft)));
+    assert!(matches!(b, Err(BarrierError::PeerLeft)));
+
+    let shard_2: Shard<Msg> = mesh.join_with(2).unwrap();
+    let (a, b, c) = futures::join!(
+        barrier.wait(&shard_0),
+        barrier.wait(&shard_1),
+        barrier.wait(&shard_2)
+    );
+    assert!(a.is_ok() && b.is_ok() && c.is_ok());
+}
+
+#[monoio::test_all(timer_enabled = true)]
+async fn barrier_fails_when_a_peer_already_left() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(2, 2).unwrap();
+    let barrier = mesh.barrier();
+
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+    shard_1.leave();
+
+    let waited =
+        monoio::time::timeout(Duration::from_secs(1), barrier.wait(&shard_0))
+            .await;
+    assert!(matches!(waited, Ok(Err(BarrierError::PeerLeft))));
+}
+
+#[monoio::test_all(timer_enabled = true)]
+async fn barrier_fails_when_the_mesh_is_shutdown() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(3, 2).unwrap();
+    let barrier = mesh.barrier();
+
+    let shards: Vec<Shard<Msg>> =
+        (0..3).map(|peer| mesh.join_with(peer).unwrap()).collect();
+
+    let shutdown = async {
+        monoio::time::sleep(Duration::from_millis(10)).await;
+        mesh.shutdown();
+    };
+    let waits = async {
+        futures::join!(barrier.wait(&shards[0]), barrier.wait(&shards[1]))
+    };
+    let waited = monoio::time::timeout(Duration::from_secs(1), waits);
+    let (waited, ()) = futures::join!(waited, shutdown);
+    let (a, b) = waited.unwrap();
+    assert!(matches!(a, Err(BarrierError::Closed)));
+    assert!(matches!(b, Err(BarrierError::Closed)));
+
+    let waited =
+        monoio::time::timeout(Duration::from_secs(1), barrier.wait(&shards[2]))
+            .await;
+    assert!(matches!(waited, Ok(Err(BarrierError::Closed))));
+}
+
+#[monoio::test_all(timer_enabled = true)]
+async fn barrier_counts_each_shard_once() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(3, 2).unwrap();
+    let barrier = mesh.barrier();
+
+    let shards: Vec<Shard<Msg>> =
+        (0..3).map(|peer| mesh.join_with(peer).unwrap()).collect();
+
+    // A second wait of the same shard doesn't stand in for the third shard.
+    let (a, b, c) = futures::join!(
+        monoio::time::timeout(
+            Duration::from_millis(10),
+            barrier.wait(&shards[0])
+        ),
+        barrier.wait(&shards[0]),
+        monoio::time::timeout(
+            Duration::from_millis(10),
+            barrier.wait(&shards[1])
+        )
+    );
+    assert!(a.is_err());
+    assert!(matches!(b, Err(BarrierError::AlreadyWaiting)));
+    assert!(c.is_err());
+
+    let (a, b, c) = futures::join!(
+        barrier.wait(&shards[0]),
+        barrier.wait(&shards[1]),
+        barrier.wait(&shards[2])
+    );
+    assert!(a.is_ok() && b.is_ok() && c.is_ok());
+}
+
+#[monoio::test_all(timer_enabled = true)]
+async fn barrier_refuses_a_shard_of_another_mesh() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(1, 2).unwrap();
+    let other = MeshBuilder::<Msg>::with_cpu(1, 2).unwrap();
+    let shard: Shard<Msg> = other.join_with(0).unwrap();
+
+    let waited = mesh.barrier().wait(&shard).await;
+    assert!(matches!(waited, Err(BarrierError::WrongMesh)));
+}
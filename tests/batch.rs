@@ -0,0 +1,90 @@
+use futures::StreamExt;
+use sharded_thread::mesh::MeshBuilder;
+use sharded_thread::shard::{SendError, Shard};
+
+#[monoio::test_all(timer_enabled = true)]
+async fn send_batches() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(2, 2).unwrap().with_capacity(4);
+
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+    let mut receiver = shard_1.receiver().unwrap();
+
+    shard_0.send_batch_to(0..3, 1).unwrap();
+    let rejected = shard_0.send_batch_to(3..5, 1);
+    assert!(
+        matches!(rejected, Err(SendError::Full(ref batch)) if batch == &[3, 4])
+    );
+
+    let mut received = Vec::new();
+    assert_eq!(receiver.recv_batch(&mut received, 8), 3);
+    received.sort();
+    assert_eq!(received, [0, 1, 2]);
+}
+
+#[monoio::test_all(timer_enabled = true)]
+async fn send_buffer_flushes() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(2, 2).unwrap();
+
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+    let mut receiver = shard_1.receiver().unwrap();
+
+    let buffer = shard_0.send_buffer(2);
+    assert!(matches!(buffer.push(0, 2), Err(SendError::WrongShard(_))));
+
+    buffer.push(0, 1).unwrap();
+    assert!(receiver.try_recv().is_err());
+    buffer.push(1, 1).unwrap();
+
+    let mut received = Vec::new();
+    assert_eq!(receiver.recv_batch(&mut received, 8), 2);
+
+    // The value is sent once the task yields, before the threshold.
+    let (received, flushed) = buffer
+        .flush_on_poll(async {
+            buffer.push(2, 1).unwrap();
+            receiver.next().await
+        })
+        .await;
+    assert_eq!(received, Some(2));
+    assert!(flushed.is_ok());
+}
+
+#[monoio::test_all(timer_enabled = true)]
+async fn send_buffer_gives_back_unsent_values() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(2, 2).unwrap();
+
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+    shard_1.close();
+
+    let buffer = shard_0.send_buffer(4);
+    buffer.push(0, 1).unwrap();
+    buffer.push(1, 1).unwrap();
+
+    let failures = buffer.flush().unwrap_err().into_failures();
+    assert!(matches!(
+        &failures[..],
+        [(1, SendError::Closed(batch))] if batch == &[0, 1]
+    ));
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "must be flushed before it is dropped")]
+fn send_buffer_must_be_flushed() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(2, 2).unwrap();
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+
+    let buffer = shard_0.send_buffer(4);
+    buffer.push(0, 1).unwrap();
+}
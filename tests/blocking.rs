@@ -0,0 +1,67 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use sharded_thread::mesh::MeshBuilder;
+use sharded_thread::shard::Shard;
+use sharded_thread::RecvTimeoutError;
+
+#[test]
+fn receive_on_a_plain_thread() {
+    type Msg = usize;
+
+    let mesh = Arc::new(MeshBuilder::<Msg>::with_cpu(2, 2).unwrap());
+
+    let (checked, timed_out) = std::sync::mpsc::channel();
+    let handle = std::thread::spawn({
+        let mesh = mesh.clone();
+        move || {
+            let writer: Shard<Msg> = mesh.join_with(1).unwrap();
+            let mut receiver = writer.receiver().unwrap();
+
+            assert_eq!(
+                receiver.recv_timeout(Duration::from_millis(10)),
+                Err(RecvTimeoutError::Timeout)
+            );
+            checked.send(()).unwrap();
+
+            let mut received = Vec::new();
+            while let Some(val) = receiver.recv_blocking() {
+                received.push(val);
+            }
+
+            received
+        }
+    });
+
+    let shard: Shard<Msg> = mesh.join_with(0).unwrap();
+    timed_out.recv().unwrap();
+    for val in 0..4 {
+        shard.send_to(val, 1).unwrap();
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    mesh.shutdown();
+
+    let mut received = handle.join().unwrap();
+    received.sort();
+    assert_eq!(received, [0, 1, 2, 3]);
+}
+
+#[test]
+fn wait_without_deadline_for_huge_timeouts() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(2, 2).unwrap();
+
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+    let mut receiver = shard_1.receiver().unwrap();
+
+    shard_0.send_to(3, 1).unwrap();
+    assert_eq!(receiver.recv_timeout(Duration::MAX), Ok(3));
+
+    mesh.shutdown();
+    assert_eq!(
+        receiver.recv_timeout(Duration::MAX),
+        Err(RecvTimeoutError::Closed)
+    );
+}
@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use futures::StreamExt;
+use sharded_thread::mesh::MeshBuilder;
+use sharded_thread::shard::{SendError, Shard};
+
+#[monoio::test_all(timer_enabled = true)]
+async fn bounded_shard_applies_backpressure() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(2, 2).unwrap().with_capacity(2);
+
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+    let mut receiver = shard_1.receiver().unwrap();
+
+    shard_0.try_send_to(1, 1).unwrap();
+    shard_0.send_to(2, 1).unwrap();
+
+    let full = shard_0.try_send_to(3, 1);
+    assert!(matches!(full, Err(SendError::Full(3))));
+
+    let blocked = monoio::time::timeout(
+        Duration::from_millis(10),
+        shard_0.send_to_async(3, 1),
+    )
+    .await;
+    assert!(blocked.is_err());
+
+    let (sent, _) =
+        futures::join!(shard_0.send_to_async(3, 1), receiver.next());
+    assert!(sent.is_ok());
+
+    let mut received = vec![
+        receiver.next().await.unwrap(),
+        receiver.next().await.unwrap(),
+    ];
+    received.sort();
+    assert!(received == [2, 3] || received == [1, 3]);
+}
+
+#[monoio::test_all(timer_enabled = true)]
+async fn failed_sends_give_the_value_back() {
+    type Msg = String;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(3, 2).unwrap().with_capacity(1);
+
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+    let _shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+
+    let error = shard_0.send_to("wrong".to_string(), 3).unwrap_err();
+    assert!(matches!(error, SendError::WrongShard(_)));
+    assert_eq!(error.into_inner(), "wrong");
+
+    let error = shard_0.send_to("not joined".to_string(), 2).unwrap_err();
+    assert!(matches!(error, SendError::NotJoined(_)));
+    assert!(error.is_retryable());
+    assert_eq!(error.into_inner(), "not joined");
+
+    shard_0.send_to("first".to_string(), 1).unwrap();
+    let error = shard_0.send_to("full".to_string(), 1).unwrap_err();
+    assert!(matches!(error, SendError::Full(_)));
+    assert_eq!(error.inner(), "full");
+
+    mesh.shutdown();
+    let error = shard_0
+        .send_to_unchecked("closed".to_string(), 1)
+        .unwrap_err();
+    assert!(matches!(error, SendError::Closed(_)));
+    assert!(!error.is_retryable());
+    assert_eq!(error.into_inner(), "closed");
+}
+
+#[test]
+#[should_panic(expected = "before any peer joins it")]
+fn capacity_is_refused_once_a_peer_joined() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(2, 2).unwrap();
+    let _shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+
+    let _mesh = mesh.with_capacity(4);
+}
@@ -0,0 +1,61 @@
+use std::sync::Arc;
+
+use futures::StreamExt;
+use sharded_thread::mesh::MeshBuilder;
+use sharded_thread::shard::{SendError, Shard};
+
+#[monoio::test_all(timer_enabled = true)]
+async fn broadcast_to_every_shard() {
+    type Msg = String;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(4, 2).unwrap();
+
+    let shards: Vec<Shard<Msg>> =
+        (0..3).map(|peer| mesh.join_with(peer).unwrap()).collect();
+    let mut receivers: Vec<_> = shards
+        .iter()
+        .map(|shard| shard.receiver().unwrap())
+        .collect();
+
+    // The fourth shard did not join, it is reported.
+    let error = shards[0].broadcast("all".to_string()).unwrap_err();
+    let failures = error.into_failures();
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].0, 3);
+    assert!(
+        matches!(&failures[0].1, SendError::NotJoined(val) if val == "all")
+    );
+
+    shards[1]
+        .broadcast_to_others("others".to_string())
+        .unwrap_err();
+    shards[2].multicast("some".to_string(), &[0, 1]).unwrap();
+
+    assert_eq!(receivers[0].next().await.unwrap(), "all");
+    assert_eq!(receivers[1].next().await.unwrap(), "all");
+    assert_eq!(receivers[2].next().await.unwrap(), "all");
+
+    assert_eq!(receivers[0].next().await.unwrap(), "others");
+    assert_eq!(receivers[2].next().await.unwrap(), "others");
+
+    assert_eq!(receivers[0].next().await.unwrap(), "some");
+    assert_eq!(receivers[1].next().await.unwrap(), "some");
+}
+
+#[monoio::test_all(timer_enabled = true)]
+async fn broadcast_a_shared_value() {
+    type Msg = Arc<Vec<u8>>;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(2, 2).unwrap();
+
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+    let mut receiver_0 = shard_0.receiver().unwrap();
+    let mut receiver_1 = shard_1.receiver().unwrap();
+
+    shard_0.broadcast_shared(vec![0; 1024]).unwrap();
+
+    let val_0 = receiver_0.next().await.unwrap();
+    let val_1 = receiver_1.next().await.unwrap();
+    assert!(Arc::ptr_eq(&val_0, &val_1));
+}
@@ -0,0 +1,87 @@
+use futures::StreamExt;
+use sharded_thread::mesh::MeshBuilder;
+use sharded_thread::shard::{SendError, Shard};
+
+#[derive(Debug, PartialEq)]
+enum Control {
+    Reload,
+    Shutdown,
+}
+
+#[derive(Debug, PartialEq)]
+struct Data(usize);
+
+#[monoio::test_all(timer_enabled = true)]
+async fn channels_share_the_peers_of_the_mesh() {
+    let mesh = MeshBuilder::<String>::with_cpu(3, 2)
+        .unwrap()
+        .with_channel::<Control>()
+        .with_channel::<Data>();
+
+    let shard_0: Shard<String> = mesh.join_with(0).unwrap();
+    let shard_1: Shard<String> = mesh.join_with(1).unwrap();
+
+    assert!(shard_0.channel::<u64>().is_none());
+
+    let control = shard_0.channel::<Control>().unwrap();
+    let data = shard_0.channel::<Data>().unwrap();
+
+    shard_0.send_to("hello".to_string(), 1).unwrap();
+    control.send_to(Control::Reload, 1).unwrap();
+    data.send_to(Data(1), 1).unwrap();
+    data.send_to(Data(2), 1).unwrap();
+
+    // The peers are shared: peer 2 did not join any channel yet.
+    assert!(matches!(
+        control.send_to(Control::Reload, 2),
+        Err(SendError::NotJoined(Control::Reload))
+    ));
+    assert!(matches!(
+        data.send_to(Data(3), 3),
+        Err(SendError::WrongShard(Data(3)))
+    ));
+
+    mesh.shutdown();
+
+    let receiver = shard_1.receiver().unwrap();
+    let control_receiver =
+        shard_1.channel::<Control>().unwrap().receiver().unwrap();
+    let data_receiver = shard_1.channel::<Data>().unwrap().receiver().unwrap();
+    assert!(shard_1.channel::<Data>().unwrap().receiver().is_none());
+
+    assert_eq!(receiver.collect::<Vec<_>>().await, ["hello"]);
+    assert_eq!(
+        control_receiver.collect::<Vec<_>>().await,
+        [Control::Reload]
+    );
+    let mut received = data_receiver.collect::<Vec<_>>().await;
+    received.sort_by_key(|data| data.0);
+    assert_eq!(received, [Data(1), Data(2)]);
+
+    assert!(matches!(
+        control.send_to(Control::Shutdown, 1),
+        Err(SendError::Closed(Control::Shutdown))
+    ));
+}
+
+#[test]
+fn shard_can_move_to_another_thread() {
+    let mesh = MeshBuilder::<String>::with_cpu(2, 2)
+        .unwrap()
+        .with_channel::<Control>();
+
+    let shard_0: Shard<String> = mesh.join_with(0).unwrap();
+    let shard_1: Shard<String> = mesh.join_with(1).unwrap();
+
+    std::thread::spawn(move || {
+        let control = shard_0.channel::<Control>().unwrap();
+        control.send_to(Control::Reload, 1).unwrap();
+        drop(shard_0.remote_tasks());
+    })
+    .join()
+    .unwrap();
+
+    let mut receiver =
+        shard_1.channel::<Control>().unwrap().receiver().unwrap();
+    assert_eq!(receiver.try_recv(), Ok(Control::Reload));
+}
@@ -42,15 +42,19 @@ fn contention() {
                     let mut receiver = shard.receiver().unwrap();
 
                     let send_to = (peer + 1) % cpus;
-                    shard.send_to_unchecked(peer, send_to);
+                    shard
+                        .wait_all_joined_timeout(monoio::time::sleep(
+                            Duration::from_secs(1),
+                        ))
+                        .await
+                        .unwrap();
+                    shard.send_to(peer, send_to).unwrap();
 
                     let result = monoio::time::timeout(
                         Duration::from_millis(20),
                         async move {
-                            while let Some(val) = receiver.next().await {
+                            if let Some(val) = receiver.next().await {
                                 println!("Received {val} on CPU {peer}");
-                                // In this example we break at the begining
-                                return;
                             }
                         },
                     )
@@ -0,0 +1,55 @@
+#![cfg(target_os = "linux")]
+
+use std::os::fd::AsRawFd;
+
+use sharded_thread::mesh::MeshBuilder;
+use sharded_thread::shard::Shard;
+
+#[monoio::test_all(timer_enabled = true)]
+async fn eventfd_coalesces_sends() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(2, 2)
+        .unwrap()
+        .with_capacity(8)
+        .with_eventfd()
+        .unwrap();
+
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+    let mut receiver = shard_1.receiver().unwrap();
+
+    let eventfd = receiver.eventfd().unwrap();
+    assert!(eventfd.as_raw_fd() >= 0);
+    assert_eq!(eventfd.clear(), 0);
+
+    for val in 0..3 {
+        shard_0.send_to(val, 1).unwrap();
+    }
+    assert_eq!(eventfd.clear(), 1);
+
+    let mut received = Vec::new();
+    assert_eq!(receiver.recv_batch(&mut received, 8), 3);
+
+    shard_0.send_to(3, 1).unwrap();
+    assert_eq!(receiver.eventfd().unwrap().clear(), 1);
+}
+
+#[monoio::test_all(timer_enabled = true)]
+async fn eventfd_is_written_for_remote_tasks() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(2, 2)
+        .unwrap()
+        .with_eventfd()
+        .unwrap();
+
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+    let receiver = shard_1.receiver().unwrap();
+    let eventfd = receiver.eventfd().unwrap();
+    assert_eq!(eventfd.clear(), 0);
+
+    let _task = shard_0.submit_to(|| 42, 1);
+    assert_eq!(eventfd.clear(), 1);
+}
@@ -0,0 +1,156 @@
+use futures::StreamExt;
+use sharded_thread::mesh::{JoinError, JoinOptions, MeshBuilder};
+use sharded_thread::shard::{SendError, Shard};
+
+#[monoio::test_all(timer_enabled = true)]
+async fn rejoin_after_leaving() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(2, 2).unwrap();
+
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+
+    shard_0.send_to(1, 1).unwrap();
+    shard_1.leave();
+    assert_eq!(mesh.members(), [0]);
+
+    let rejected = shard_0.send_to(2, 1);
+    assert!(matches!(rejected, Err(SendError::NotJoined(2))));
+
+    // Values sent before leaving are reclaimed by the new shard.
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+    assert_eq!(mesh.members(), [0, 1]);
+    shard_0.send_to(3, 1).unwrap();
+
+    let mut receiver = shard_1.receiver().unwrap();
+    let mut received = vec![
+        receiver.next().await.unwrap(),
+        receiver.next().await.unwrap(),
+    ];
+    received.sort();
+    assert_eq!(received, [1, 3]);
+}
+
+#[monoio::test_all(timer_enabled = true)]
+async fn receivers_end_when_their_shard_leaves() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(2, 2).unwrap();
+
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+
+    let mut old_receiver = shard_1.receiver().unwrap();
+    shard_0.send_to(7, 1).unwrap();
+    shard_1.leave();
+    assert_eq!(old_receiver.next().await, None);
+
+    // The replacement gets the value left by the previous shard.
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+    shard_0.send_to(8, 1).unwrap();
+    assert!(old_receiver.try_recv().is_err());
+
+    let mut receiver = shard_1.receiver().unwrap();
+    assert_eq!(receiver.next().await, Some(7));
+    assert_eq!(receiver.next().await, Some(8));
+}
+
+#[monoio::test_all(timer_enabled = true)]
+async fn rejoin_after_closing() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(2, 2).unwrap();
+
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+
+    shard_0.send_to(1, 1).unwrap();
+    shard_1.close();
+    assert!(matches!(shard_0.send_to(2, 1), Err(SendError::Closed(2))));
+    shard_1.leave();
+
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+    shard_0.send_to(3, 1).unwrap();
+
+    let mut receiver = shard_1.receiver().unwrap();
+    assert_eq!(receiver.try_recv(), Ok(1));
+    assert_eq!(receiver.try_recv(), Ok(3));
+    assert!(receiver.try_recv().is_err());
+
+    // A shut down mesh stays closed.
+    mesh.shutdown();
+    assert!(matches!(mesh.join_with(0), Err(JoinError::Closed)));
+    assert!(matches!(shard_0.send_to(4, 1), Err(SendError::Closed(4))));
+}
+
+#[monoio::test_all(timer_enabled = true)]
+async fn rejoin_discarding_pending_values() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(2, 2).unwrap();
+
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+
+    shard_0.send_to(1, 1).unwrap();
+    drop(shard_1);
+
+    let shard_1: Shard<Msg> = mesh
+        .join_with_options(1, JoinOptions::default().discard_pending(true))
+        .unwrap();
+    shard_0.send_to(2, 1).unwrap();
+
+    let mut receiver = shard_1.receiver().unwrap();
+    assert_eq!(receiver.next().await, Some(2));
+}
+
+#[monoio::test_all(timer_enabled = true)]
+async fn track_each_peer() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(4, 2).unwrap();
+
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+    let _shard_3: Shard<Msg> = mesh.join_with(3).unwrap();
+    assert_eq!(mesh.members(), [0, 3]);
+    assert_eq!(mesh.nr_members(), 2);
+
+    assert!(matches!(
+        shard_0.send_to(1, 1),
+        Err(SendError::NotJoined(1))
+    ));
+    assert!(shard_0.send_to(3, 3).is_ok());
+
+    let twice = mesh.join_with(3);
+    assert!(matches!(twice, Err(JoinError::AlreadyJoined(3))));
+}
+
+#[test]
+fn join_errors() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(2, 2).unwrap();
+
+    let out_of_range = mesh.join_with(2);
+    assert!(matches!(
+        out_of_range,
+        Err(JoinError::OutOfRange {
+            peer: 2,
+            nr_peers: 2
+        })
+    ));
+
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+    let shard_0: Shard<Msg> = mesh.join_next().unwrap();
+    assert!(matches!(mesh.join_next(), Err(JoinError::Full)));
+
+    drop(shard_1);
+    let shard_1: Shard<Msg> = mesh.join_next().unwrap();
+    assert!(shard_0.send_to(1, 1).is_ok());
+    assert!(shard_1.send_to(0, 0).is_ok());
+
+    drop(shard_1);
+    mesh.shutdown();
+    assert!(matches!(mesh.join_next(), Err(JoinError::Closed)));
+}
@@ -7,7 +7,7 @@ use futures::StreamExt;
 use monoio::io::{AsyncReadRentExt, AsyncWriteRent};
 use monoio::net::{TcpListener, TcpStream};
 use sharded_thread::mesh::MeshBuilder;
-use sharded_thread::shard::Shard;
+use sharded_thread::shard::{SendError, Shard};
 
 cfg_if::cfg_if! {
     if #[cfg(target_os = "linux")] {
@@ -62,10 +62,15 @@ fn ensure_messages_are_sent_through_the_shard() {
     }
 
     let pos = mesh.members();
-    assert_eq!(pos, cpus);
+    assert_eq!(pos.len(), cpus);
 
-    mesh.send_to(0, 12).unwrap();
-    mesh.send_to(0, 1).unwrap();
+    let first = mesh.peer_id(0).unwrap();
+    mesh.send_to(12, first).unwrap();
+    mesh.send_to(1, first).unwrap();
+
+    let other = MeshBuilder::<Msg>::new(2).unwrap();
+    let rejected = mesh.send_to(3, other.peer_id(1).unwrap()).unwrap_err();
+    assert!(matches!(rejected, SendError::WrongShard(3)));
 }
 
 #[test]
@@ -0,0 +1,77 @@
+use std::sync::{Arc, Barrier};
+
+use sharded_thread::mesh::MeshBuilder;
+use sharded_thread::shard::{SendError, Shard};
+
+#[test]
+fn every_pair_is_received_in_order() {
+    // (source, sequence number)
+    type Msg = (usize, usize);
+
+    const PEERS: usize = 4;
+    const COUNT: usize = 10_000;
+
+    let mesh = Arc::new(
+        MeshBuilder::<Msg>::with_cpu(PEERS, 2)
+            .unwrap()
+            .with_capacity(8)
+            .with_fifo_per_sender(),
+    );
+    assert!(mesh.is_fifo_per_sender());
+
+    let joined = Arc::new(Barrier::new(PEERS));
+    let handles = (0..PEERS)
+        .map(|peer| {
+            let mesh = mesh.clone();
+            let joined = joined.clone();
+            std::thread::spawn(move || {
+                let shard: Shard<Msg> = mesh.join_with(peer).unwrap();
+                let mut receiver = shard.receiver().unwrap();
+                joined.wait();
+
+                let mut next = [0; PEERS];
+                let mut receive = |next: &mut [usize; PEERS]| {
+                    while let Ok((source, seq)) = receiver.try_recv() {
+                        assert_eq!(
+                            seq, next[source],
+                            "{source} -> {peer} out of order"
+                        );
+                        next[source] += 1;
+                    }
+                };
+
+                for seq in 0..COUNT {
+                    for dest in (0..PEERS).filter(|&dest| dest != peer) {
+                        let mut val = (peer, seq);
+                        while let Err(err) = shard.send_to(val, dest) {
+                            assert!(matches!(err, SendError::Full(_)));
+                            val = err.into_inner();
+                            receive(&mut next);
+                            std::thread::yield_now();
+                        }
+                    }
+                }
+
+                while next
+                    .iter()
+                    .enumerate()
+                    .any(|(source, &n)| source != peer && n < COUNT)
+                {
+                    receive(&mut next);
+                    std::thread::yield_now();
+                }
+
+                // Keep the shard alive until every peer received everything.
+                joined.wait();
+                next
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for (peer, handle) in handles.into_iter().enumerate() {
+        let next = handle.join().unwrap();
+        for (source, n) in next.into_iter().enumerate() {
+            assert_eq!(n, if source == peer { 0 } else { COUNT });
+        }
+    }
+}
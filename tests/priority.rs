@@ -0,0 +1,90 @@
+use sharded_thread::mesh::MeshBuilder;
+use sharded_thread::shard::{SendError, Shard};
+
+#[test]
+fn higher_priorities_are_received_first() {
+    type Msg = &'static str;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(2, 2)
+        .unwrap()
+        .with_priorities(3);
+
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+
+    shard_0.send_to("data", 1).unwrap();
+    shard_0.send_to_with_priority("reload", 1, 1).unwrap();
+    shard_0.send_to_with_priority("shutdown", 1, 2).unwrap();
+    // Above the highest priority, sent with the highest one.
+    shard_0.send_to_with_priority("ping", 1, 10).unwrap();
+
+    let mut receiver = shard_1.receiver().unwrap();
+    let mut received = Vec::new();
+    receiver.recv_batch(&mut received, 8);
+
+    assert_eq!(received.len(), 4);
+    assert!(received[..2].contains(&"shutdown"));
+    assert!(received[..2].contains(&"ping"));
+    assert_eq!(received[2..], ["reload", "data"]);
+}
+
+#[test]
+fn lower_priorities_are_not_starved() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(2, 2)
+        .unwrap()
+        .with_priorities(2);
+
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+
+    shard_0.send_to(0, 1).unwrap();
+    for val in 1..=100 {
+        shard_0.send_to_with_priority(val, 1, 1).unwrap();
+    }
+
+    let mut receiver = shard_1.receiver().unwrap();
+    let mut received = Vec::new();
+    receiver.recv_batch(&mut received, 128);
+
+    assert_eq!(received.len(), 101);
+    let low = received.iter().position(|&val| val == 0).unwrap();
+    assert_eq!(low, 32);
+}
+
+#[test]
+fn each_priority_has_its_own_capacity() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(2, 2)
+        .unwrap()
+        .with_capacity(2)
+        .with_priorities(2);
+
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+
+    shard_0.send_to(0, 1).unwrap();
+    shard_0.send_to(1, 1).unwrap();
+    assert!(matches!(shard_0.send_to(2, 1), Err(SendError::Full(2))));
+
+    // The lowest priority being full doesn't block the highest one.
+    shard_0.send_to_with_priority(10, 1, 1).unwrap();
+    shard_0.send_to_with_priority(11, 1, 1).unwrap();
+    assert!(matches!(
+        shard_0.send_to_with_priority(12, 1, 1),
+        Err(SendError::Full(12))
+    ));
+
+    let mut receiver = shard_1.receiver().unwrap();
+    assert_eq!(receiver.try_recv().ok(), Some(10));
+
+    // Only the highest priority got some room.
+    assert!(matches!(shard_0.send_to(2, 1), Err(SendError::Full(2))));
+    shard_0.send_to_with_priority(12, 1, 1).unwrap();
+
+    let mut received = Vec::new();
+    receiver.recv_batch(&mut received, 8);
+    assert_eq!(received, [11, 12, 0, 1]);
+}
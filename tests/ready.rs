@@ -0,0 +1,57 @@
+use std::time::Duration;
+
+use sharded_thread::mesh::MeshBuilder;
+use sharded_thread::shard::Shard;
+
+#[monoio::test_all(timer_enabled = true)]
+async fn ready_reports_missing_peers() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(3, 2).unwrap();
+
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+
+    let not_ready = shard_1
+        .wait_all_joined_timeout(monoio::time::sleep(Duration::from_millis(10)))
+        .await
+        .unwrap_err();
+    assert_eq!(not_ready.missing(), [0, 2]);
+
+    let join_others = async {
+        monoio::time::sleep(Duration::from_millis(10)).await;
+        (mesh.join_with(0).unwrap(), mesh.join_with(2).unwrap())
+    };
+    let (ready, (shard_0, _shard_2)) =
+        futures::join!(shard_1.wait_all_joined(), join_others);
+    assert!(ready.is_ok());
+
+    assert!(mesh
+        .ready_timeout(monoio::time::sleep(Duration::from_millis(10)))
+        .await
+        .is_ok());
+    assert!(shard_0.send_to(2, 2).is_ok());
+}
+
+#[monoio::test_all(timer_enabled = true)]
+async fn shutdown_wakes_the_tasks_waiting_for_peers() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(3, 2).unwrap();
+
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+
+    let shutdown = async {
+        monoio::time::sleep(Duration::from_millis(10)).await;
+        mesh.shutdown();
+    };
+    let (ready, ()) = futures::join!(shard_1.wait_all_joined(), shutdown);
+    assert!(ready.is_err());
+
+    assert!(mesh.ready().await.is_err());
+    let not_ready = mesh
+        .ready_timeout(monoio::time::sleep(Duration::from_secs(60)))
+        .await
+        .unwrap_err();
+    assert!(not_ready.is_closed());
+    assert_eq!(not_ready.missing(), [0, 2]);
+}
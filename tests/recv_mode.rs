@@ -0,0 +1,57 @@
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::task::noop_waker;
+use futures::StreamExt;
+use sharded_thread::mesh::{JoinOptions, MeshBuilder};
+use sharded_thread::shard::Shard;
+use sharded_thread::RecvMode;
+
+/// Send a value to shard 1 from another thread after a short delay.
+fn send_later(mesh: &Arc<MeshBuilder<usize>>, val: usize) {
+    let mesh = mesh.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(5));
+        mesh.send_to(val, mesh.peer_id(1).unwrap()).unwrap();
+    });
+}
+
+#[test]
+fn spin_before_parking() {
+    let mesh = Arc::new(MeshBuilder::<usize>::with_cpu(2, 2).unwrap());
+
+    let options = JoinOptions::default()
+        .recv_mode(RecvMode::Spin(Duration::from_secs(5)));
+    let shard: Shard<usize> = mesh.join_with_options(1, options).unwrap();
+    let mut receiver = shard.receiver().unwrap();
+
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+
+    // The value is received by spinning, without being woken up.
+    send_later(&mesh, 1);
+    assert_eq!(receiver.poll_next_unpin(&mut cx), Poll::Ready(Some(1)));
+}
+
+#[test]
+fn adaptive_does_not_spin_for_rare_values() {
+    let mesh = Arc::new(MeshBuilder::<usize>::with_cpu(2, 2).unwrap());
+
+    let options = JoinOptions::default()
+        .recv_mode(RecvMode::Adaptive(Duration::from_micros(100)));
+    let shard: Shard<usize> = mesh.join_with_options(1, options).unwrap();
+    let mut receiver = shard.receiver().unwrap();
+
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+
+    for val in 0..2 {
+        mesh.send_to(val, mesh.peer_id(1).unwrap()).unwrap();
+        assert_eq!(receiver.try_recv(), Ok(val));
+        std::thread::sleep(Duration::from_millis(5));
+    }
+
+    send_later(&mesh, 2);
+    assert_eq!(receiver.poll_next_unpin(&mut cx), Poll::Pending);
+}
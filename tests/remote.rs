@@ -0,0 +1,92 @@
+use std::sync::Arc;
+
+use sharded_thread::mesh::MeshBuilder;
+use sharded_thread::remote::RemoteError;
+use sharded_thread::shard::Shard;
+
+cfg_if::cfg_if! {
+    if #[cfg(target_os = "linux")] {
+        type Driver = monoio::IoUringDriver;
+    } else {
+        type Driver = monoio::LegacyDriver;
+    }
+}
+
+#[test]
+fn run_a_closure_on_another_shard() {
+    type Msg = ();
+
+    let mesh = Arc::new(MeshBuilder::<Msg>::with_cpu(2, 2).unwrap());
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+
+    let remote = {
+        let mesh = mesh.clone();
+        std::thread::spawn(move || {
+            let mut rt = monoio::RuntimeBuilder::<Driver>::new()
+                .enable_timer()
+                .build()
+                .expect("Cannot build runtime");
+
+            let shard: Shard<Msg> = mesh.join_with(1).unwrap();
+            let remote_tasks = shard.remote_tasks().unwrap();
+            rt.block_on(remote_tasks);
+        })
+    };
+
+    let mut rt = monoio::RuntimeBuilder::<Driver>::new()
+        .enable_timer()
+        .build()
+        .expect("Cannot build runtime");
+
+    rt.block_on(async move {
+        while mesh.nr_members() < 2 {
+            monoio::time::sleep(std::time::Duration::from_millis(1)).await;
+        }
+
+        let caller = std::thread::current().id();
+        let remote_thread = shard_0
+            .submit_to(|| std::thread::current().id(), 1)
+            .await
+            .unwrap();
+        assert_ne!(remote_thread, caller);
+
+        let result = shard_0
+            .spawn_on(
+                || async {
+                    monoio::time::sleep(std::time::Duration::from_millis(1))
+                        .await;
+                    21 * 2
+                },
+                1,
+            )
+            .await;
+        assert_eq!(result.unwrap(), 42);
+
+        let panicked = shard_0.submit_to(|| panic!("remote panic"), 1).await;
+        assert!(matches!(panicked, Err(RemoteError::Panicked)));
+
+        mesh.shutdown();
+        let closed = shard_0.submit_to(|| (), 1).await;
+        // The remote shard may already have left once its tasks are done.
+        assert!(matches!(
+            closed,
+            Err(RemoteError::Closed | RemoteError::NotJoined)
+        ));
+    });
+
+    remote.join().unwrap();
+}
+
+#[monoio::test_all(timer_enabled = true)]
+async fn spawn_on_a_missing_shard() {
+    type Msg = ();
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(2, 2).unwrap();
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+
+    let not_joined = shard_0.submit_to(|| (), 1).await;
+    assert!(matches!(not_joined, Err(RemoteError::NotJoined)));
+
+    let wrong_shard = shard_0.submit_to(|| (), 2).await;
+    assert!(matches!(wrong_shard, Err(RemoteError::WrongShard)));
+}
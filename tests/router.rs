@@ -0,0 +1,68 @@
+use std::hash::BuildHasherDefault;
+
+use futures::StreamExt;
+use sharded_thread::mesh::MeshBuilder;
+use sharded_thread::router::{JumpConsistentHash, Rendezvous};
+use sharded_thread::shard::Shard;
+
+#[monoio::test_all(timer_enabled = true)]
+async fn send_values_by_key() {
+    type Msg = &'static str;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(3, 2)
+        .unwrap()
+        .with_router(JumpConsistentHash);
+
+    let shards: Vec<Shard<Msg>> =
+        (0..3).map(|peer| mesh.join_with(peer).unwrap()).collect();
+    let mut receivers: Vec<_> = shards
+        .iter()
+        .map(|shard| shard.receiver().unwrap())
+        .collect();
+
+    for key in ["foo", "bar", "baz"] {
+        let owner = mesh.shard_for_key(key);
+        assert_eq!(shards[0].shard_for_key(key), owner);
+        assert_eq!(shards[2].shard_for_key(key), owner);
+
+        shards[0].send_by_key(key, key).unwrap();
+        assert_eq!(receivers[owner].next().await, Some(key));
+    }
+}
+
+#[test]
+fn routing_is_stable_between_meshes() {
+    #[derive(Default)]
+    struct Fnv(u64);
+
+    impl std::hash::Hasher for Fnv {
+        fn finish(&self) -> u64 {
+            self.0
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            for byte in bytes {
+                self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x100000001b3);
+            }
+        }
+    }
+
+    let mesh = |nr_peers| {
+        MeshBuilder::<()>::with_cpu(nr_peers, 2)
+            .unwrap()
+            .with_router(Rendezvous)
+            .with_hasher(BuildHasherDefault::<Fnv>::default())
+    };
+
+    let (small, big) = (mesh(4), mesh(5));
+    let moved = (0..1_000)
+        .filter(|key| small.shard_for_key(key) != big.shard_for_key(key))
+        .count();
+    assert!(moved < 400);
+}
+
+#[test]
+fn a_mesh_needs_a_peer_to_route_keys() {
+    let error = MeshBuilder::<usize>::with_cpu(0, 2).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+}
@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use futures::StreamExt;
+use sharded_thread::mesh::MeshBuilder;
+use sharded_thread::rpc::{CallError, Request};
+use sharded_thread::shard::Shard;
+
+#[monoio::test_all(timer_enabled = true)]
+async fn call_another_shard() {
+    type Msg = Request<usize, usize>;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(2, 2).unwrap();
+
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+    let mut receiver = shard_1.receiver().unwrap();
+
+    let handler = monoio::spawn(async move {
+        while let Some(request) = receiver.next().await {
+            let (val, responder) = request.into_parts();
+            match val {
+                // Never reply, but keep the responder to see the caller left.
+                0 => {
+                    monoio::time::sleep(Duration::from_millis(20)).await;
+                    assert!(responder.is_canceled());
+                }
+                // Drop the responder without replying.
+                1 => {}
+                val => responder.reply(val * 2).unwrap(),
+            }
+        }
+    });
+
+    assert_eq!(shard_0.call(21, 1).await.unwrap(), 42);
+
+    let timeout = shard_0
+        .call_timeout(0, 1, monoio::time::sleep(Duration::from_millis(5)))
+        .await;
+    assert!(matches!(timeout, Err(CallError::Timeout)));
+
+    let dropped = shard_0.call(1, 1).await;
+    assert!(matches!(dropped, Err(CallError::Dropped)));
+
+    mesh.shutdown();
+    let closed = shard_0.call(2, 1).await;
+    assert!(matches!(closed, Err(CallError::Closed)));
+
+    handler.await;
+}
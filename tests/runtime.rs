@@ -0,0 +1,110 @@
+#![cfg(feature = "monoio")]
+
+use futures::StreamExt;
+use sharded_thread::mesh::{JoinError, MeshBuilder};
+use sharded_thread::runtime::{MeshRuntime, RuntimeError};
+
+#[test]
+fn launch_a_shard_per_cpu() {
+    let runtime = MeshRuntime::<usize>::new(vec![0, 0, 0])
+        .unwrap()
+        .with_runtime(|builder| builder.with_entries(256));
+
+    let handle = runtime
+        .launch(|shard| async move {
+            shard.wait_all_joined().await.unwrap();
+
+            let next = (shard.id().index() + 1) % shard.peers();
+            shard.send_to(shard.id().index(), next).unwrap();
+
+            let mut receiver = shard.receiver().unwrap();
+            receiver.next().await
+        })
+        .unwrap();
+
+    assert_eq!(handle.join().unwrap(), [Some(2), Some(0), Some(1)]);
+}
+
+#[test]
+fn report_the_shard_which_failed() {
+    let handle = MeshRuntime::<usize>::new(vec![0, 0])
+        .unwrap()
+        .launch(|shard| async move {
+            if shard.id().index() == 1 {
+                panic!("shard 1 failed");
+            }
+        })
+        .unwrap();
+
+    let error = handle.join().unwrap_err();
+    assert_eq!(error.shard(), 1);
+    let RuntimeError::Panicked { payload, .. } = error else {
+        panic!("the shard should have panicked");
+    };
+    assert_eq!(payload.downcast_ref::<&str>(), Some(&"shard 1 failed"));
+
+    let mesh = MeshBuilder::<usize>::with_cpu(1, 2).unwrap();
+    let handle = MeshRuntime::with_mesh(mesh, vec![0, 0])
+        .launch(|_shard| async {})
+        .unwrap();
+
+    assert!(matches!(
+        handle.join(),
+        Err(RuntimeError::Join {
+            shard: 1,
+            error: JoinError::OutOfRange { peer: 1, .. }
+        })
+    ));
+}
+
+#[test]
+fn a_failed_shard_shuts_the_mesh_down() {
+    let handle = MeshRuntime::<usize>::new(vec![0, 0, 0])
+        .unwrap()
+        .launch(|shard| async move {
+            if shard.id().index() == 2 {
+                panic!("shard 2 failed");
+            }
+
+            // Ends once the mesh is shut down.
+            shard.receiver().unwrap().next().await
+        })
+        .unwrap();
+    assert_eq!(handle.join().unwrap_err().shard(), 2);
+
+    let mesh = MeshBuilder::<usize>::with_cpu(3, 2).unwrap();
+    let handle = MeshRuntime::with_mesh(mesh, vec![0, 0, 0, 0])
+        .launch(|shard| async move { shard.receiver().unwrap().next().await })
+        .unwrap();
+    assert!(matches!(
+        handle.join(),
+        Err(RuntimeError::Join {
+            shard: 3,
+            error: JoinError::OutOfRange { peer: 3, .. }
+        })
+    ));
+}
+
+#[test]
+fn launch_a_fixed_mesh() {
+    let mesh = MeshBuilder::<usize, 2>::fixed_with_cpu(2).unwrap();
+    let handle = MeshRuntime::with_mesh(mesh, vec![0, 0])
+        .launch(|shard| async move { shard.id().index() })
+        .unwrap();
+    assert_eq!(handle.join().unwrap(), [0, 1]);
+
+    let mesh = MeshBuilder::<usize, 1>::fixed_with_cpu(2).unwrap();
+    let handle = MeshRuntime::with_mesh(mesh, vec![0, 0])
+        .launch(|_shard| async {})
+        .unwrap();
+    assert!(matches!(
+        handle.join(),
+        Err(RuntimeError::Join {
+            shard: 1,
+            error: JoinError::OutOfRange {
+                peer: 1,
+                nr_peers: 1
+            }
+        })
+    ));
+}
@@ -0,0 +1,173 @@
+use futures::{SinkExt, StreamExt};
+use sharded_thread::mesh::{JoinError, MeshBuilder};
+use sharded_thread::remote::RemoteError;
+use sharded_thread::rpc::{CallError, Request};
+use sharded_thread::shard::{SendError, Shard};
+
+#[test]
+fn ids_only_work_with_their_mesh() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg, 2>::fixed_with_cpu(2).unwrap();
+    let other = MeshBuilder::<Msg>::with_cpu(2, 2).unwrap();
+
+    let first = mesh.id::<0>();
+    let second = mesh.id::<1>();
+    assert_eq!(second.index(), 1);
+    assert_eq!(mesh.peer_ids().collect::<Vec<_>>(), [first, second]);
+    assert_eq!(mesh.peer_id(1), Some(second));
+    assert_eq!(mesh.peer_id(2), None);
+    assert_ne!(other.peer_id(1), Some(second));
+
+    let shard_0: Shard<Msg> = mesh.join(first).unwrap();
+    let shard_1: Shard<Msg> = mesh.join(second).unwrap();
+    assert!(matches!(
+        other.join(first).map(drop),
+        Err(JoinError::WrongMesh)
+    ));
+
+    shard_0.send_to(42, second).unwrap();
+    let foreign = other.peer_id(1).unwrap();
+    assert!(matches!(
+        shard_0.send_to(43, foreign),
+        Err(SendError::WrongShard(43))
+    ));
+
+    let mut receiver = shard_1.receiver().unwrap();
+    assert_eq!(receiver.try_recv(), Ok(42));
+    assert!(receiver.try_recv().is_err());
+}
+
+#[test]
+fn shard_knows_its_topology() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(3, 2).unwrap();
+
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+    assert_eq!(shard_1.id(), mesh.peer_id(1).unwrap());
+    assert_eq!(shard_1.peer_id(2), mesh.peer_id(2));
+    assert_eq!(shard_1.peer_id(3), None);
+    assert_eq!(shard_1.id().to_string(), "1");
+    assert_eq!(shard_1.peers(), 3);
+    assert_eq!(shard_1.joined_peers(), 1);
+    assert_eq!(
+        shard_1
+            .other_peers()
+            .map(|id| id.index())
+            .collect::<Vec<_>>(),
+        [0, 2]
+    );
+
+    let shard_2: Shard<Msg> = mesh.join_with(2).unwrap();
+    assert_eq!(shard_1.joined_peers(), 2);
+    assert_eq!(format!("{shard_2:?}"), "Shard { id: 2, .. }");
+
+    for peer in shard_2.other_peers() {
+        assert!(matches!(
+            shard_2.send_to(peer.index(), peer),
+            Ok(()) | Err(SendError::NotJoined(0))
+        ));
+    }
+    let mut receiver = shard_1.receiver().unwrap();
+    assert_eq!(receiver.try_recv(), Ok(1));
+}
+
+#[monoio::test_all(timer_enabled = true)]
+async fn every_send_takes_an_id() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(2, 2)
+        .unwrap()
+        .with_priorities(2)
+        .with_channel::<String>();
+    let other = MeshBuilder::<Msg>::with_cpu(2, 2).unwrap();
+    let foreign = other.peer_id(1).unwrap();
+
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+    let to = mesh.peer_id(1).unwrap();
+
+    shard_0.try_send_to(1, to).unwrap();
+    shard_0.send_to_with_priority(2, to, 1).unwrap();
+    shard_0.send_to_async(3, to).await.unwrap();
+    shard_0.send_batch_to([4, 5], to).unwrap();
+    shard_0.multicast(6, &[to]).unwrap();
+    shard_0
+        .sink_to(to)
+        .send_all(&mut futures::stream::iter([Ok(7)]))
+        .await
+        .unwrap();
+    let buffer = shard_0.send_buffer(2);
+    buffer.push(8, to).unwrap();
+    buffer.flush_to(to).unwrap();
+
+    assert!(matches!(
+        shard_0.try_send_to(1, foreign),
+        Err(SendError::WrongShard(1))
+    ));
+    assert!(matches!(
+        shard_0.send_batch_to([2], foreign),
+        Err(SendError::WrongShard(_))
+    ));
+    let failures = shard_0.multicast(9, &[to, foreign]).unwrap_err();
+    assert!(matches!(
+        failures.failures(),
+        [(1, SendError::WrongShard(9))]
+    ));
+    assert!(matches!(
+        shard_0.sink_to(foreign).send(10).await,
+        Err(SendError::WrongShard(_))
+    ));
+    assert!(matches!(
+        buffer.push(11, foreign),
+        Err(SendError::WrongShard(_))
+    ));
+
+    let mut receiver = shard_1.receiver().unwrap();
+    let mut received = Vec::new();
+    receiver.recv_batch(&mut received, 16);
+    received.sort();
+    assert_eq!(received, [1, 2, 3, 4, 5, 6, 7, 8, 9]);
+
+    let channel = shard_0.channel::<String>().unwrap();
+    channel.send_to("id".to_string(), to).unwrap();
+    channel
+        .send_to_async("async".to_string(), to)
+        .await
+        .unwrap();
+    assert!(channel.send_to("id".to_string(), foreign).is_err());
+    let mut strings = shard_1.channel::<String>().unwrap().receiver().unwrap();
+    assert_eq!(strings.try_recv().as_deref(), Ok("id"));
+    assert_eq!(strings.try_recv().as_deref(), Ok("async"));
+
+    let remote = shard_0.spawn_on(|| async {}, foreign).await;
+    assert!(matches!(remote, Err(RemoteError::WrongShard)));
+    let remote = shard_0.submit_to(|| (), foreign).await;
+    assert!(matches!(remote, Err(RemoteError::WrongShard)));
+}
+
+#[monoio::test_all(timer_enabled = true)]
+async fn call_a_shard_by_id() {
+    type Msg = Request<usize, usize>;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(2, 2).unwrap();
+    let other = MeshBuilder::<Msg>::with_cpu(2, 2).unwrap();
+
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+
+    let serve = async {
+        let mut requests = shard_1.receiver().unwrap();
+        let (request, responder) = requests.next().await.unwrap().into_parts();
+        responder.reply(request * 2).unwrap();
+    };
+    let (response, ()) = futures::join!(shard_0.call(21, shard_1.id()), serve);
+    assert_eq!(response.unwrap(), 42);
+
+    let foreign = other.peer_id(1).unwrap();
+    assert!(matches!(
+        shard_0.call(1, foreign).await,
+        Err(CallError::WrongShard)
+    ));
+}
@@ -0,0 +1,44 @@
+use futures::StreamExt;
+use sharded_thread::mesh::MeshBuilder;
+use sharded_thread::shard::{SendError, Shard};
+
+#[monoio::test_all(timer_enabled = true)]
+async fn receiver_ends_after_shutdown() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(2, 2).unwrap();
+
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+    let receiver = shard_1.receiver().unwrap();
+
+    shard_0.send_to(1, 1).unwrap();
+    shard_0.send_to(2, 1).unwrap();
+
+    mesh.shutdown();
+
+    let rejected = shard_0.send_to(3, 1);
+    assert!(matches!(rejected, Err(SendError::Closed(3))));
+
+    let mut received: Vec<Msg> = receiver.collect().await;
+    received.sort();
+    assert_eq!(received, [1, 2]);
+}
+
+#[monoio::test_all(timer_enabled = true)]
+async fn closed_shard_rejects_values() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(2, 2).unwrap();
+
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+    let mut receiver = shard_1.receiver().unwrap();
+
+    shard_1.close();
+
+    let rejected = shard_0.send_to_async(1, 1).await;
+    assert_eq!(rejected.unwrap_err().into_inner(), 1);
+    assert!(shard_0.send_to(2, 0).is_ok());
+    assert_eq!(receiver.next().await, None);
+}
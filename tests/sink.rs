@@ -0,0 +1,56 @@
+use futures::{SinkExt, StreamExt};
+use sharded_thread::mesh::MeshBuilder;
+use sharded_thread::shard::{SendError, Shard};
+
+#[monoio::test_all(timer_enabled = true)]
+async fn forward_a_shard_into_another() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(3, 2).unwrap().with_capacity(4);
+
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+    let shard_2: Shard<Msg> = mesh.join_with(2).unwrap();
+
+    let receiver_1 = shard_1.receiver().unwrap();
+    let receiver_2 = shard_2.receiver().unwrap();
+
+    let produce = async {
+        let sent = futures::stream::iter(0..16)
+            .map(Ok)
+            .forward(shard_0.sink_to(1))
+            .await;
+        shard_1.close();
+        sent
+    };
+    let forward = receiver_1
+        .map(Ok)
+        .forward(shard_1.sink_to(2).with_buffer_size(3));
+    let consume = receiver_2.take(16).collect::<Vec<_>>();
+
+    let (produced, forwarded, mut received) =
+        futures::join!(produce, forward, consume);
+    assert!(produced.is_ok());
+    assert!(forwarded.is_ok());
+
+    received.sort();
+    assert_eq!(received, (0..16).collect::<Vec<_>>());
+}
+
+#[monoio::test_all(timer_enabled = true)]
+async fn sink_gives_the_values_back() {
+    type Msg = usize;
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(2, 2).unwrap();
+
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+
+    let mut sink = shard_0.sink_to(1);
+    sink.feed(1).await.unwrap();
+    sink.feed(2).await.unwrap();
+
+    let not_joined = sink.flush().await;
+    assert!(
+        matches!(not_joined, Err(SendError::NotJoined(vals)) if vals == [1, 2])
+    );
+}
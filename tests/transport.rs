@@ -0,0 +1,90 @@
+use std::sync::Arc;
+
+use sharded_thread::mesh::{MeshBuilder, Transport};
+use sharded_thread::shard::{SendError, Shard};
+
+#[test]
+fn rings_keep_the_order_of_each_sender() {
+    type Msg = (usize, usize);
+
+    let mesh = MeshBuilder::<Msg>::with_cpu(3, 2)
+        .unwrap()
+        .with_transport(Transport::Rings)
+        .with_capacity(4);
+
+    let shard_0: Shard<Msg> = mesh.join_with(0).unwrap();
+    let shard_1: Shard<Msg> = mesh.join_with(1).unwrap();
+    let shard_2: Shard<Msg> = mesh.join_with(2).unwrap();
+
+    for seq in 0..4 {
+        shard_0.send_to((0, seq), 2).unwrap();
+        shard_1.send_to((1, seq), 2).unwrap();
+    }
+
+    // Each ring is bounded on its own.
+    assert!(matches!(
+        shard_0.send_to((0, 4), 2),
+        Err(SendError::Full(_))
+    ));
+    shard_0.send_to((0, 0), 1).unwrap();
+
+    let mut receiver = shard_2.receiver().unwrap();
+    let mut received = Vec::new();
+    assert_eq!(receiver.recv_batch(&mut received, 16), 8);
+
+    for source in 0..2 {
+        let from_source = received
+            .iter()
+            .filter(|(from, _)| *from == source)
+            .map(|(_, seq)| *seq)
+            .collect::<Vec<_>>();
+        assert_eq!(from_source, [0, 1, 2, 3]);
+    }
+
+    shard_0.send_to((0, 4), 2).unwrap();
+    assert_eq!(receiver.try_recv(), Ok((0, 4)));
+}
+
+#[test]
+fn rings_refuse_values_once_closed() {
+    type Msg = usize;
+
+    let mesh = Arc::new(
+        MeshBuilder::<Msg>::with_cpu(3, 2)
+            .unwrap()
+            .with_transport(Transport::Rings)
+            .with_capacity(16),
+    );
+    let shard_2: Shard<Msg> = mesh.join_with(2).unwrap();
+    let mut receiver = shard_2.receiver().unwrap();
+
+    let senders = (0..2)
+        .map(|peer| {
+            let shard: Shard<Msg> = mesh.join_with(peer).unwrap();
+            std::thread::spawn(move || {
+                let mut sent = 0;
+                loop {
+                    match shard.send_to(peer, 2) {
+                        Ok(()) => sent += 1,
+                        Err(SendError::Full(_)) => std::thread::yield_now(),
+                        Err(_) => return sent,
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut received = 0;
+    while receiver.recv_blocking().is_some() {
+        received += 1;
+        if received == 1_000 {
+            mesh.shutdown();
+        }
+    }
+
+    let sent = senders
+        .into_iter()
+        .map(|sender| sender.join().unwrap())
+        .sum::<usize>();
+    assert_eq!(sent, received);
+}